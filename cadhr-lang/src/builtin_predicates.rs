@@ -0,0 +1,904 @@
+//! 書き換え時に評価される組み込み述語（リスト操作など）
+
+use std::cmp::Ordering;
+
+use crate::parse::{
+    ArithOp, ERROR_TERM_DEPTH, FixedPoint, ScopedTerm, Term, list, number, number_int, pipe_into,
+    string_lit, struc,
+};
+use crate::term_processor::{BuiltinPredicate, BuiltinPredicateResult, PredicateContext};
+use crate::term_rewrite::{ScopedEnv, eval_arith, resolve};
+
+fn ground_list_items<'a>(
+    term: &'a ScopedTerm,
+    predicate: &str,
+    arg_index: usize,
+) -> Result<&'a [ScopedTerm], String> {
+    match term {
+        Term::List { items, tail: None } => Ok(items),
+        _ => Err(format!(
+            "{}: argument {} must be a proper list, got {}",
+            predicate,
+            arg_index,
+            term.elided(ERROR_TERM_DEPTH)
+        )),
+    }
+}
+
+// ============================================================
+// list_to_set/2, subtract/3
+// ============================================================
+
+fn eval_list_to_set(args: &[ScopedTerm], _ctx: &PredicateContext) -> BuiltinPredicateResult {
+    let items = ground_list_items(&args[0], "list_to_set", 0)?;
+    let mut set: Vec<ScopedTerm> = Vec::with_capacity(items.len());
+    for item in items {
+        if !set.contains(item) {
+            set.push(item.clone());
+        }
+    }
+    Ok(vec![(1, list(set, None))])
+}
+
+fn eval_subtract(args: &[ScopedTerm], _ctx: &PredicateContext) -> BuiltinPredicateResult {
+    let items = ground_list_items(&args[0], "subtract", 0)?;
+    let removed = ground_list_items(&args[1], "subtract", 1)?;
+    let diff = items
+        .iter()
+        .filter(|item| !removed.contains(item))
+        .cloned()
+        .collect();
+    Ok(vec![(2, list(diff, None))])
+}
+
+inventory::submit! {
+    BuiltinPredicate { name: "list_to_set", arity: 2, resolve_args: false, eval: eval_list_to_set }
+}
+inventory::submit! {
+    BuiltinPredicate { name: "subtract", arity: 3, resolve_args: false, eval: eval_subtract }
+}
+
+// ============================================================
+// last/2, append/2
+// ============================================================
+
+fn eval_last(args: &[ScopedTerm], _ctx: &PredicateContext) -> BuiltinPredicateResult {
+    let items = ground_list_items(&args[0], "last", 0)?;
+    let last = items.last().ok_or("last: list is empty")?;
+    Ok(vec![(1, last.clone())])
+}
+
+fn eval_append(args: &[ScopedTerm], _ctx: &PredicateContext) -> BuiltinPredicateResult {
+    let lists = ground_list_items(&args[0], "append", 0)?;
+    let mut flat = Vec::new();
+    for l in lists {
+        flat.extend_from_slice(ground_list_items(l, "append", 0)?);
+    }
+    Ok(vec![(1, list(flat, None))])
+}
+
+inventory::submit! {
+    BuiltinPredicate { name: "last", arity: 2, resolve_args: false, eval: eval_last }
+}
+inventory::submit! {
+    BuiltinPredicate { name: "append", arity: 2, resolve_args: false, eval: eval_append }
+}
+
+// ============================================================
+// length/2
+// ============================================================
+
+/// List が未束縛で N が与えられた場合は、N 個の新しい変数からなるリストを作る
+fn eval_length(args: &[ScopedTerm], _ctx: &PredicateContext) -> BuiltinPredicateResult {
+    match &args[0] {
+        Term::List { items, tail: None } => Ok(vec![(1, number_int(items.len() as i64))]),
+        Term::Var { name, scope, .. } => {
+            let n = length_of(&args[1], "length", 1)?
+                .ok_or("length: arguments are insufficiently instantiated")?;
+            let fresh = (0..n)
+                .map(|i| Term::Var {
+                    name: format!("__{}_{}", name, i),
+                    scope: *scope,
+                    default_value: None,
+                    min: None,
+                    max: None,
+                    span: None,
+                })
+                .collect();
+            Ok(vec![(0, list(fresh, None))])
+        }
+        other => Err(format!(
+            "length: argument 0 must be a proper list or unbound, got {}",
+            other.elided(ERROR_TERM_DEPTH)
+        )),
+    }
+}
+
+inventory::submit! {
+    BuiltinPredicate { name: "length", arity: 2, resolve_args: false, eval: eval_length }
+}
+
+// ============================================================
+// keysort/2
+// ============================================================
+
+/// 項の標準順序: Var < Number < Atom < String < 複合項（アリティ、名前、引数の順）< List
+fn standard_order(a: &ScopedTerm, b: &ScopedTerm) -> Ordering {
+    fn rank(t: &ScopedTerm) -> u8 {
+        match t {
+            Term::Var { .. } => 0,
+            Term::Number { .. } => 1,
+            Term::Struct { args, .. } if args.is_empty() => 2,
+            Term::StringLit { .. } => 3,
+            Term::Struct { .. } | Term::InfixExpr { .. } | Term::Constraint { .. } => 4,
+            Term::List { .. } => 5,
+        }
+    }
+    fn compare_all(a: &[ScopedTerm], b: &[ScopedTerm]) -> Ordering {
+        a.iter()
+            .zip(b)
+            .map(|(x, y)| standard_order(x, y))
+            .find(|o| o.is_ne())
+            .unwrap_or_else(|| a.len().cmp(&b.len()))
+    }
+    match (a, b) {
+        (Term::Var { name: n1, .. }, Term::Var { name: n2, .. }) => n1.cmp(n2),
+        (Term::Number { value: v1 }, Term::Number { value: v2 }) => v1.cmp(v2),
+        (Term::StringLit { value: v1 }, Term::StringLit { value: v2 }) => v1.cmp(v2),
+        (
+            Term::Struct {
+                functor: f1,
+                args: a1,
+                ..
+            },
+            Term::Struct {
+                functor: f2,
+                args: a2,
+                ..
+            },
+        ) => a1
+            .len()
+            .cmp(&a2.len())
+            .then_with(|| f1.cmp(f2))
+            .then_with(|| compare_all(a1, a2)),
+        (Term::List { items: i1, .. }, Term::List { items: i2, .. }) => compare_all(i1, i2),
+        _ => rank(a).cmp(&rank(b)),
+    }
+}
+
+/// `Key - Value` または `k(Key, Value)` のキー
+fn pair_key(term: &ScopedTerm) -> Option<&ScopedTerm> {
+    match term {
+        Term::InfixExpr {
+            op: ArithOp::Sub,
+            left,
+            ..
+        } => Some(left),
+        Term::Struct { functor, args, .. } if functor == "k" && args.len() == 2 => Some(&args[0]),
+        _ => None,
+    }
+}
+
+/// キーの標準順序で安定ソートする（同じキーの要素は元の順序を保つ）
+fn eval_keysort(args: &[ScopedTerm], _ctx: &PredicateContext) -> BuiltinPredicateResult {
+    let items = ground_list_items(&args[0], "keysort", 0)?;
+    let mut keyed = items
+        .iter()
+        .map(|item| {
+            pair_key(item).map(|key| (key, item)).ok_or_else(|| {
+                format!(
+                    "keysort: expected Key - Value or k(Key, Value), got {}",
+                    item.elided(ERROR_TERM_DEPTH)
+                )
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    keyed.sort_by(|(k1, _), (k2, _)| standard_order(k1, k2));
+    let sorted = keyed.into_iter().map(|(_, item)| item.clone()).collect();
+    Ok(vec![(1, list(sorted, None))])
+}
+
+inventory::submit! {
+    BuiltinPredicate { name: "keysort", arity: 2, resolve_args: false, eval: eval_keysort }
+}
+
+// ============================================================
+// numlist/3
+// ============================================================
+
+fn integer_arg(term: &ScopedTerm, predicate: &str, arg_index: usize) -> Result<i64, String> {
+    match term {
+        Term::Number { value } => value.to_i64_checked(),
+        _ => None,
+    }
+    .ok_or_else(|| {
+        format!(
+            "{}: argument {} must be an integer, got {}",
+            predicate,
+            arg_index,
+            term.elided(ERROR_TERM_DEPTH)
+        )
+    })
+}
+
+fn eval_numlist(args: &[ScopedTerm], _ctx: &PredicateContext) -> BuiltinPredicateResult {
+    let low = integer_arg(&args[0], "numlist", 0)?;
+    let high = integer_arg(&args[1], "numlist", 1)?;
+    if low > high {
+        return Err(format!(
+            "numlist: low {} is greater than high {}",
+            low, high
+        ));
+    }
+    let items = (low..=high).map(number_int).collect();
+    Ok(vec![(2, list(items, None))])
+}
+
+inventory::submit! {
+    BuiltinPredicate { name: "numlist", arity: 3, resolve_args: false, eval: eval_numlist }
+}
+
+// ============================================================
+// close/3
+// ============================================================
+
+fn number_arg(term: &ScopedTerm, predicate: &str, arg_index: usize) -> Result<FixedPoint, String> {
+    eval_arith(term, true).value().ok_or_else(|| {
+        format!(
+            "{}: argument {} must be a number, got {}",
+            predicate,
+            arg_index,
+            term.elided(ERROR_TERM_DEPTH)
+        )
+    })
+}
+
+/// 単一化は FixedPoint の完全一致なので、除算の切り捨て誤差を許して比べたいときに使う
+fn eval_close(args: &[ScopedTerm], _ctx: &PredicateContext) -> BuiltinPredicateResult {
+    let a = number_arg(&args[0], "close", 0)?;
+    let b = number_arg(&args[1], "close", 1)?;
+    let tolerance = number_arg(&args[2], "close", 2)?;
+    if tolerance.raw() < 0 {
+        return Err(format!("close: tolerance {} is negative", tolerance));
+    }
+    if (a - b).raw().abs() > tolerance.raw() {
+        return Err(format!(
+            "close: {} and {} differ by more than {}",
+            a, b, tolerance
+        ));
+    }
+    Ok(vec![])
+}
+
+inventory::submit! {
+    BuiltinPredicate { name: "close", arity: 3, resolve_args: false, eval: eval_close }
+}
+
+// ============================================================
+// apply_all/3
+// ============================================================
+
+fn eval_apply_all(args: &[ScopedTerm], _ctx: &PredicateContext) -> BuiltinPredicateResult {
+    let transform = match &args[0] {
+        t @ Term::Struct { .. } => t,
+        other => {
+            return Err(format!(
+                "apply_all: argument 0 must be a functor term, got {}",
+                other.elided(ERROR_TERM_DEPTH)
+            ));
+        }
+    };
+    let items = ground_list_items(&args[1], "apply_all", 1)?;
+    let applied = items
+        .iter()
+        .map(|item| pipe_into(item.clone(), transform.clone()))
+        .collect();
+    Ok(vec![(2, list(applied, None))])
+}
+
+inventory::submit! {
+    BuiltinPredicate { name: "apply_all", arity: 3, resolve_args: false, eval: eval_apply_all }
+}
+
+// ============================================================
+// findall/3, union_list/2
+// ============================================================
+
+/// バックトラックしないため、Goal は解を列挙できる member(X, List) に限る。
+/// List の各要素を X に入れた Template を集める
+fn eval_findall(args: &[ScopedTerm], _ctx: &PredicateContext) -> BuiltinPredicateResult {
+    let (var, items) = match &args[1] {
+        Term::Struct {
+            functor,
+            args: goal_args,
+            ..
+        } if functor == "member" && goal_args.len() == 2 => (
+            &goal_args[0],
+            ground_list_items(&goal_args[1], "findall", 1)?,
+        ),
+        other => {
+            return Err(format!(
+                "findall: goal must be member(X, List), got {}",
+                other.elided(ERROR_TERM_DEPTH)
+            ));
+        }
+    };
+    let Term::Var { name, scope, .. } = var else {
+        return Err(format!(
+            "findall: member/2 element must be a variable, got {}",
+            var.elided(ERROR_TERM_DEPTH)
+        ));
+    };
+    let found = items
+        .iter()
+        .map(|item| {
+            let mut env = ScopedEnv::new();
+            env.insert(*scope, name.clone(), item.clone());
+            resolve(&args[0], &env)
+        })
+        .collect();
+    Ok(vec![(2, list(found, None))])
+}
+
+inventory::submit! {
+    BuiltinPredicate { name: "findall", arity: 3, resolve_args: false, eval: eval_findall }
+}
+
+/// union_list([A, B, C], S): S = union(A, union(B, C))。空リストは empty
+fn eval_union_list(args: &[ScopedTerm], _ctx: &PredicateContext) -> BuiltinPredicateResult {
+    let items = ground_list_items(&args[0], "union_list", 0)?;
+    let union = items
+        .iter()
+        .cloned()
+        .rev()
+        .reduce(|acc, item| struc("union".to_string(), vec![item, acc]))
+        .unwrap_or_else(|| struc("empty".to_string(), vec![]));
+    Ok(vec![(1, union)])
+}
+
+inventory::submit! {
+    BuiltinPredicate { name: "union_list", arity: 2, resolve_args: false, eval: eval_union_list }
+}
+
+// ============================================================
+// string_concat/3, sub_atom/5
+// ============================================================
+
+/// アトム・文字列・数値をテキストとして取り出す。未束縛なら None
+fn text_of(term: &ScopedTerm, predicate: &str, arg_index: usize) -> Result<Option<String>, String> {
+    match term {
+        Term::Var { .. } => Ok(None),
+        Term::StringLit { value } => Ok(Some(value.clone())),
+        Term::Struct { functor, args, .. } if args.is_empty() => Ok(Some(functor.clone())),
+        Term::Number { value } => Ok(Some(value.to_string())),
+        _ => Err(format!(
+            "{}: argument {} must be an atom or string, got {}",
+            predicate,
+            arg_index,
+            term.elided(ERROR_TERM_DEPTH)
+        )),
+    }
+}
+
+fn length_of(
+    term: &ScopedTerm,
+    predicate: &str,
+    arg_index: usize,
+) -> Result<Option<usize>, String> {
+    match term {
+        Term::Var { .. } => Ok(None),
+        Term::Number { value } => value
+            .to_i64_checked()
+            .and_then(|n| usize::try_from(n).ok())
+            .map(Some)
+            .ok_or_else(|| {
+                format!(
+                    "{}: argument {} must be a non-negative integer, got {}",
+                    predicate,
+                    arg_index,
+                    term.elided(ERROR_TERM_DEPTH)
+                )
+            }),
+        _ => Err(format!(
+            "{}: argument {} must be a non-negative integer, got {}",
+            predicate,
+            arg_index,
+            term.elided(ERROR_TERM_DEPTH)
+        )),
+    }
+}
+
+fn eval_string_concat(args: &[ScopedTerm], _ctx: &PredicateContext) -> BuiltinPredicateResult {
+    let a = text_of(&args[0], "string_concat", 0)?;
+    let b = text_of(&args[1], "string_concat", 1)?;
+    let c = text_of(&args[2], "string_concat", 2)?;
+    match (a, b, c) {
+        (Some(a), Some(b), _) => Ok(vec![(2, string_lit(a + &b))]),
+        (Some(a), None, Some(c)) => match c.strip_prefix(&a) {
+            Some(rest) => Ok(vec![(1, string_lit(rest.to_string()))]),
+            None => Err(format!(
+                "string_concat: {:?} does not start with {:?}",
+                c, a
+            )),
+        },
+        (None, Some(b), Some(c)) => match c.strip_suffix(&b) {
+            Some(rest) => Ok(vec![(0, string_lit(rest.to_string()))]),
+            None => Err(format!("string_concat: {:?} does not end with {:?}", c, b)),
+        },
+        _ => Err("string_concat: arguments are insufficiently instantiated".to_string()),
+    }
+}
+
+/// バックトラックしないため、Sub が与えられた場合は最初の出現位置を返す
+fn eval_sub_atom(args: &[ScopedTerm], _ctx: &PredicateContext) -> BuiltinPredicateResult {
+    let atom: Vec<char> = text_of(&args[0], "sub_atom", 0)?
+        .ok_or("sub_atom: argument 0 must be bound")?
+        .chars()
+        .collect();
+    let before = length_of(&args[1], "sub_atom", 1)?;
+    let length = length_of(&args[2], "sub_atom", 2)?;
+    let after = length_of(&args[3], "sub_atom", 3)?;
+    let sub = text_of(&args[4], "sub_atom", 4)?;
+    let total = atom.len();
+    let out_of_range = || "sub_atom: substring is out of range".to_string();
+
+    let (before, length) = match (before, length, after, sub) {
+        (_, _, _, Some(sub)) => {
+            let sub: Vec<char> = sub.chars().collect();
+            let pos = (0..=total.saturating_sub(sub.len()))
+                .find(|&i| atom[i..].starts_with(&sub))
+                .ok_or_else(|| {
+                    format!(
+                        "sub_atom: {} does not occur in the atom",
+                        args[4].elided(ERROR_TERM_DEPTH)
+                    )
+                })?;
+            (pos, sub.len())
+        }
+        (Some(b), Some(l), _, None) => (b, l),
+        (Some(b), None, Some(a), None) => (b, total.checked_sub(b + a).ok_or_else(out_of_range)?),
+        (None, Some(l), Some(a), None) => (total.checked_sub(l + a).ok_or_else(out_of_range)?, l),
+        _ => return Err("sub_atom: arguments are insufficiently instantiated".to_string()),
+    };
+    if before.checked_add(length).is_none_or(|end| end > total) {
+        return Err(out_of_range());
+    }
+    let sub: String = atom[before..before + length].iter().collect();
+    Ok(vec![
+        (1, number_int(before as i64)),
+        (2, number_int(length as i64)),
+        (3, number_int((total - before - length) as i64)),
+        (4, struc(sub, vec![])),
+    ])
+}
+
+inventory::submit! {
+    BuiltinPredicate { name: "string_concat", arity: 3, resolve_args: false, eval: eval_string_concat }
+}
+inventory::submit! {
+    BuiltinPredicate { name: "sub_atom", arity: 5, resolve_args: false, eval: eval_sub_atom }
+}
+
+// ============================================================
+// atom_number/2
+// ============================================================
+
+/// バックトラックしないため、数値として読めないアトムは他の述語と同様に失敗（エラー）とする
+fn eval_atom_number(args: &[ScopedTerm], _ctx: &PredicateContext) -> BuiltinPredicateResult {
+    match (&args[0], &args[1]) {
+        (Term::Var { .. }, Term::Number { value }) => {
+            Ok(vec![(0, struc(value.to_string(), vec![]))])
+        }
+        (Term::Var { .. }, _) => {
+            Err("atom_number: arguments are insufficiently instantiated".to_string())
+        }
+        (atom, _) => {
+            let text = text_of(atom, "atom_number", 0)?.unwrap_or_default();
+            let value: FixedPoint = text
+                .trim()
+                .parse()
+                .map_err(|e| format!("atom_number: {}", e))?;
+            Ok(vec![(1, number(value))])
+        }
+    }
+}
+
+inventory::submit! {
+    BuiltinPredicate { name: "atom_number", arity: 2, resolve_args: false, eval: eval_atom_number }
+}
+
+// ============================================================
+// char_code/2, char_range/3
+// ============================================================
+
+fn single_char(term: &ScopedTerm, predicate: &str, arg_index: usize) -> Result<char, String> {
+    let text = text_of(term, predicate, arg_index)?.unwrap_or_default();
+    let mut chars = text.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(c),
+        _ => Err(format!(
+            "{}: argument {} must be a single character, got {}",
+            predicate,
+            arg_index,
+            term.elided(ERROR_TERM_DEPTH)
+        )),
+    }
+}
+
+fn char_of_code(code: i64, predicate: &str) -> Result<char, String> {
+    u32::try_from(code)
+        .ok()
+        .and_then(char::from_u32)
+        .ok_or_else(|| format!("{}: {} is not a valid code point", predicate, code))
+}
+
+fn eval_char_code(args: &[ScopedTerm], _ctx: &PredicateContext) -> BuiltinPredicateResult {
+    match &args[0] {
+        Term::Var { .. } => {
+            let c = char_of_code(integer_arg(&args[1], "char_code", 1)?, "char_code")?;
+            Ok(vec![(0, struc(c.to_string(), vec![]))])
+        }
+        term => {
+            let c = single_char(term, "char_code", 0)?;
+            Ok(vec![(1, number_int(c as i64))])
+        }
+    }
+}
+
+inventory::submit! {
+    BuiltinPredicate { name: "char_code", arity: 2, resolve_args: false, eval: eval_char_code }
+}
+
+/// char_range(a, c, L): L = [a, b, c]。部品に連番のラベルを付けるのに使う
+fn eval_char_range(args: &[ScopedTerm], _ctx: &PredicateContext) -> BuiltinPredicateResult {
+    let first = single_char(&args[0], "char_range", 0)?;
+    let last = single_char(&args[1], "char_range", 1)?;
+    if first > last {
+        return Err(format!("char_range: {} comes after {}", first, last));
+    }
+    let items = (first..=last)
+        .map(|c| struc(c.to_string(), vec![]))
+        .collect();
+    Ok(vec![(2, list(items, None))])
+}
+
+inventory::submit! {
+    BuiltinPredicate { name: "char_range", arity: 3, resolve_args: false, eval: eval_char_range }
+}
+
+// ============================================================
+// term_to_atom/2
+// ============================================================
+
+/// 項をソース表記の文字列にする。逆方向は新しい変数スコープが必要なため atom_to_term/3 で行う
+fn eval_term_to_atom(args: &[ScopedTerm], _ctx: &PredicateContext) -> BuiltinPredicateResult {
+    match &args[0] {
+        Term::Var { .. } => {
+            Err("term_to_atom: argument 0 must be bound; use atom_to_term/3 to parse".to_string())
+        }
+        t => Ok(vec![(1, string_lit(format!("{:?}", t)))]),
+    }
+}
+
+inventory::submit! {
+    BuiltinPredicate { name: "term_to_atom", arity: 2, resolve_args: false, eval: eval_term_to_atom }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::{query_succeeds, query_var_binding};
+
+    #[test]
+    fn test_close_tolerates_division_rounding() {
+        assert!(!query_succeeds("", "X = 2 / 3, X = 0.67."));
+        assert!(query_succeeds("", "close(0.67, 2 / 3, 0.01)."));
+        assert!(query_succeeds("", "close(3.33, 10 / 3, 0.01)."));
+        assert!(!query_succeeds("", "close(3.3, 10 / 3, 0.01)."));
+        assert!(!query_succeeds("", "close(X, 1, 0.01)."));
+        assert!(!query_succeeds("", "close(1, 1, -0.01)."));
+    }
+
+    #[test]
+    fn test_length_measures_list() {
+        assert_eq!(query_var_binding("", "length([a, b], N).", "N"), "2");
+        assert_eq!(query_var_binding("", "length([], N).", "N"), "0");
+        assert!(!query_succeeds("", "length([a, b], 3)."));
+        assert!(!query_succeeds("", "length(L, N)."));
+        assert!(!query_succeeds("", "length(L, -1)."));
+    }
+
+    #[test]
+    fn test_length_allocates_fresh_variables() {
+        let l = query_var_binding("", "length(L, 3).", "L");
+        let Some(inner) = l.strip_prefix('[').and_then(|r| r.strip_suffix(']')) else {
+            panic!("Expected list, got {}", l);
+        };
+        let names: Vec<&str> = inner.split(", ").collect();
+        assert_eq!(names.len(), 3);
+        assert!(names.iter().all(|n| n.starts_with("__L_")));
+        assert!(
+            names
+                .iter()
+                .enumerate()
+                .all(|(i, n)| !names[..i].contains(n))
+        );
+
+        assert_eq!(
+            query_var_binding("", "length(L, 2), list_to_set(L, [a, b]).", "L"),
+            "[a, b]"
+        );
+    }
+
+    #[test]
+    fn test_list_to_set() {
+        assert_eq!(
+            query_var_binding("", "list_to_set([a, b, a, c], S).", "S"),
+            "[a, b, c]"
+        );
+        assert_eq!(query_var_binding("", "list_to_set([], S).", "S"), "[]");
+        assert_eq!(
+            query_var_binding("", "list_to_set([f(1), f(2), f(1.0)], S).", "S"),
+            "[f(1), f(2)]"
+        );
+    }
+
+    #[test]
+    fn test_list_to_set_checks_output() {
+        assert!(query_succeeds("", "list_to_set([a, a], [a])."));
+        assert!(!query_succeeds("", "list_to_set([a, b], [a])."));
+    }
+
+    #[test]
+    fn test_subtract() {
+        assert_eq!(
+            query_var_binding("", "subtract([a, b, c], [b], D).", "D"),
+            "[a, c]"
+        );
+        assert_eq!(
+            query_var_binding("", "subtract([a, b, a], [a], D).", "D"),
+            "[b]"
+        );
+        assert_eq!(query_var_binding("", "subtract([a], [], D).", "D"), "[a]");
+    }
+
+    #[test]
+    fn test_list_predicates_require_proper_list() {
+        assert!(!query_succeeds("", "list_to_set(X, S)."));
+        assert!(!query_succeeds("", "subtract([a | T], [b], D)."));
+    }
+
+    #[test]
+    fn test_apply_all_placeholder() {
+        assert_eq!(
+            query_var_binding(
+                "",
+                "apply_all(translate(_, 0, 0, 5), [cube(1, 1, 1), sphere(1)], Out).",
+                "Out"
+            ),
+            "[translate(cube(1, 1, 1), 0, 0, 5), translate(sphere(1), 0, 0, 5)]"
+        );
+        assert_eq!(
+            query_var_binding("", "apply_all(f(_), [], Out).", "Out"),
+            "[]"
+        );
+    }
+
+    #[test]
+    fn test_apply_all_prepends_without_placeholder() {
+        assert_eq!(
+            query_var_binding("", "apply_all(scale(2, 2, 2), [a, b], Out).", "Out"),
+            "[scale(a, 2, 2, 2), scale(b, 2, 2, 2)]"
+        );
+        assert!(!query_succeeds("", "apply_all(5, [a], Out)."));
+    }
+
+    #[test]
+    fn test_findall_over_member() {
+        assert_eq!(
+            query_var_binding(
+                "",
+                "findall(step(Y, Y), member(Y, [0, 2]), Steps).",
+                "Steps"
+            ),
+            "[step(0, 0), step(2, 2)]"
+        );
+        assert_eq!(
+            query_var_binding(
+                "",
+                "numlist(1, 3, Ns), findall(f(N), member(N, Ns), Out).",
+                "Out"
+            ),
+            "[f(1), f(2), f(3)]"
+        );
+        assert!(!query_succeeds("", "findall(X, foo(X), Out)."));
+    }
+
+    #[test]
+    fn test_union_list() {
+        assert_eq!(
+            query_var_binding("", "union_list([a, b, c], S).", "S"),
+            "union(a, union(b, c))"
+        );
+        assert_eq!(query_var_binding("", "union_list([a], S).", "S"), "a");
+        assert_eq!(query_var_binding("", "union_list([], S).", "S"), "empty");
+    }
+
+    #[test]
+    fn test_string_concat() {
+        assert_eq!(
+            query_var_binding("", "string_concat(bolt_, m6, C).", "C"),
+            "\"bolt_m6\""
+        );
+        assert_eq!(
+            query_var_binding("", "string_concat(\"M\", 6, C).", "C"),
+            "\"M6\""
+        );
+        assert_eq!(
+            query_var_binding("", "string_concat(bolt_, B, \"bolt_m6\").", "B"),
+            "\"m6\""
+        );
+        assert!(!query_succeeds("", "string_concat(nut_, B, \"bolt_m6\")."));
+        assert!(!query_succeeds("", "string_concat(A, B, \"bolt_m6\")."));
+    }
+
+    #[test]
+    fn test_sub_atom() {
+        assert_eq!(
+            query_var_binding("", "sub_atom(bolt_m6, 5, 2, A, S).", "S"),
+            "m6"
+        );
+        assert_eq!(
+            query_var_binding("", "sub_atom(bolt_m6, 5, 2, A, S).", "A"),
+            "0"
+        );
+        assert_eq!(
+            query_var_binding("", "sub_atom(bolt_m6, 0, L, 3, S).", "S"),
+            "bolt"
+        );
+        assert_eq!(
+            query_var_binding("", "sub_atom(bolt_m6, B, L, A, m6).", "B"),
+            "5"
+        );
+        assert_eq!(
+            query_var_binding("", "sub_atom(bolt_m6, B, 1, 0, S).", "S"),
+            "6"
+        );
+        assert!(!query_succeeds("", "sub_atom(bolt_m6, 5, 3, A, S)."));
+        assert!(!query_succeeds("", "sub_atom(bolt_m6, B, L, A, nut)."));
+        assert!(!query_succeeds("", "sub_atom(bolt_m6, B, L, A, S)."));
+    }
+
+    #[test]
+    fn test_numlist() {
+        assert_eq!(
+            query_var_binding("", "numlist(1, 5, L).", "L"),
+            "[1, 2, 3, 4, 5]"
+        );
+        assert_eq!(
+            query_var_binding("", "numlist(-1, 1, L).", "L"),
+            "[-1, 0, 1]"
+        );
+        assert_eq!(query_var_binding("", "numlist(3, 3, L).", "L"), "[3]");
+    }
+
+    #[test]
+    fn test_numlist_invalid() {
+        assert!(!query_succeeds("", "numlist(5, 1, L)."));
+        assert!(!query_succeeds("", "numlist(1, 2.5, L)."));
+        assert!(!query_succeeds("", "numlist(a, 3, L)."));
+        assert!(!query_succeeds("", "numlist(L, 3, [1, 2, 3])."));
+    }
+
+    #[test]
+    fn test_last() {
+        assert_eq!(query_var_binding("", "last([a, b, c], X).", "X"), "c");
+        assert_eq!(query_var_binding("", "last([[1, 2]], X).", "X"), "[1, 2]");
+        assert!(!query_succeeds("", "last([], X)."));
+        assert!(!query_succeeds("", "last([a | T], X)."));
+    }
+
+    #[test]
+    fn test_append_list_of_lists() {
+        assert_eq!(
+            query_var_binding("", "append([[1, 2], [3], [4, 5]], F).", "F"),
+            "[1, 2, 3, 4, 5]"
+        );
+        assert_eq!(query_var_binding("", "append([[], []], F).", "F"), "[]");
+        assert!(!query_succeeds("", "append([[1], a], F)."));
+    }
+
+    #[test]
+    fn test_atom_number() {
+        assert_eq!(
+            query_var_binding("", "atom_number(\"3.5\", N).", "N"),
+            "3.5"
+        );
+        assert_eq!(
+            query_var_binding("", "atom_number(\"-12\", N).", "N"),
+            "-12"
+        );
+        assert!(!query_succeeds("", "atom_number(abc, N)."));
+        assert!(!query_succeeds("", "atom_number(\"1.234\", N)."));
+        assert!(!query_succeeds("", "atom_number(A, N)."));
+    }
+
+    #[test]
+    fn test_atom_number_reverse() {
+        assert_eq!(query_var_binding("", "atom_number(A, 2.5).", "A"), "2.5");
+        assert_eq!(
+            query_var_binding("", "atom_number(A, 42), string_concat(A, mm, S).", "S"),
+            "\"42mm\""
+        );
+    }
+
+    #[test]
+    fn test_char_code() {
+        assert_eq!(query_var_binding("", "char_code(a, C).", "C"), "97");
+        assert_eq!(query_var_binding("", "char_code(\"Z\", C).", "C"), "90");
+        assert_eq!(query_var_binding("", "char_code(A, 98).", "A"), "b");
+        assert!(!query_succeeds("", "char_code(ab, C)."));
+        assert!(!query_succeeds("", "char_code(A, -1)."));
+    }
+
+    #[test]
+    fn test_char_range() {
+        assert_eq!(
+            query_var_binding("", "char_range(a, c, L).", "L"),
+            "[a, b, c]"
+        );
+        assert!(!query_succeeds("", "char_range(c, a, L)."));
+    }
+
+    #[test]
+    fn test_term_to_atom() {
+        assert_eq!(
+            query_var_binding(
+                "",
+                "term_to_atom(translate(cube(1, 2, 3), 0, 0, 1.5), A).",
+                "A"
+            ),
+            "\"translate(cube(1, 2, 3), 0, 0, 1.5)\""
+        );
+        assert_eq!(
+            query_var_binding("", "term_to_atom([a, \"b\" | T], A).", "A"),
+            "\"[a, \"b\" | T]\""
+        );
+        assert!(!query_succeeds("", "term_to_atom(T, \"cube(1, 1, 1)\")."));
+    }
+
+    #[test]
+    fn test_keysort() {
+        assert_eq!(
+            query_var_binding("", "keysort([k(3, a), k(1, b), k(2, c)], S).", "S"),
+            "[k(1, b), k(2, c), k(3, a)]"
+        );
+        assert_eq!(
+            query_var_binding("", "keysort([b - 1, a - 2, 1.5 - x], S).", "S"),
+            "[(1.5 - x), (a - 2), (b - 1)]"
+        );
+        assert!(!query_succeeds("", "keysort([k(1, a), b], S)."));
+    }
+
+    #[test]
+    fn test_keysort_is_stable() {
+        assert_eq!(
+            query_var_binding(
+                "",
+                "keysort([k(2, first), k(1, x), k(2, second), k(2, third)], S).",
+                "S"
+            ),
+            "[k(1, x), k(2, first), k(2, second), k(2, third)]"
+        );
+    }
+
+    #[test]
+    fn test_argument_errors_elide_deep_terms() {
+        let mut deep: crate::parse::ScopedTerm = crate::parse::struc("leaf".to_string(), vec![]);
+        for _ in 0..100 {
+            deep = crate::parse::struc("f".to_string(), vec![deep]);
+        }
+        let message = super::ground_list_items(&deep, "sum_list", 0).unwrap_err();
+        assert!(message.contains("f(...)"), "{}", message);
+        assert!(message.len() < 200);
+    }
+}
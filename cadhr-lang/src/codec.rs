@@ -0,0 +1,412 @@
+//! パース済み Clause のバイナリエンコード（標準ライブラリ等の再パースを省くキャッシュ用）
+//!
+//! タグ付きの単純な形式で、整数はリトルエンディアン、長さは u32 で書き込む。
+
+use std::fmt;
+
+use crate::parse::{ArithOp, Bound, Clause, FixedPoint, SrcSpan, Term};
+
+const FORMAT_VERSION: u8 = 1;
+
+const TAG_VAR: u8 = 0;
+const TAG_NUMBER: u8 = 1;
+const TAG_INFIX: u8 = 2;
+const TAG_STRUCT: u8 = 3;
+const TAG_LIST: u8 = 4;
+const TAG_STRING: u8 = 5;
+const TAG_CONSTRAINT: u8 = 6;
+
+const TAG_FACT: u8 = 0;
+const TAG_RULE: u8 = 1;
+const TAG_USE: u8 = 2;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DecodeError {
+    UnexpectedEof,
+    UnsupportedVersion(u8),
+    InvalidTag { kind: &'static str, tag: u8 },
+    InvalidUtf8,
+    TrailingBytes(usize),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::UnexpectedEof => write!(f, "unexpected end of input"),
+            DecodeError::UnsupportedVersion(v) => write!(f, "unsupported format version {}", v),
+            DecodeError::InvalidTag { kind, tag } => write!(f, "invalid {} tag {}", kind, tag),
+            DecodeError::InvalidUtf8 => write!(f, "invalid UTF-8 in string"),
+            DecodeError::TrailingBytes(n) => write!(f, "{} trailing bytes after clauses", n),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+// ============================================================
+// エンコード
+// ============================================================
+
+pub fn encode_clauses(clauses: &[Clause]) -> Vec<u8> {
+    let mut out = vec![FORMAT_VERSION];
+    put_len(&mut out, clauses.len());
+    for clause in clauses {
+        put_clause(&mut out, clause);
+    }
+    out
+}
+
+fn put_len(out: &mut Vec<u8>, len: usize) {
+    out.extend_from_slice(&(len as u32).to_le_bytes());
+}
+
+fn put_str(out: &mut Vec<u8>, s: &str) {
+    put_len(out, s.len());
+    out.extend_from_slice(s.as_bytes());
+}
+
+fn put_fixed(out: &mut Vec<u8>, value: FixedPoint) {
+    out.extend_from_slice(&value.raw().to_le_bytes());
+}
+
+fn put_option<T>(out: &mut Vec<u8>, value: Option<T>, put: impl FnOnce(&mut Vec<u8>, T)) {
+    match value {
+        None => out.push(0),
+        Some(v) => {
+            out.push(1);
+            put(out, v);
+        }
+    }
+}
+
+fn put_span(out: &mut Vec<u8>, span: SrcSpan) {
+    out.extend_from_slice(&(span.start as u64).to_le_bytes());
+    out.extend_from_slice(&(span.end as u64).to_le_bytes());
+    out.extend_from_slice(&span.file_id.to_le_bytes());
+}
+
+fn put_bound(out: &mut Vec<u8>, bound: Bound) {
+    put_fixed(out, bound.value);
+    out.push(bound.inclusive as u8);
+}
+
+fn put_terms(out: &mut Vec<u8>, terms: &[Term]) {
+    put_len(out, terms.len());
+    for t in terms {
+        put_term(out, t);
+    }
+}
+
+fn put_term(out: &mut Vec<u8>, term: &Term) {
+    match term {
+        Term::Var {
+            name,
+            scope: (),
+            default_value,
+            min,
+            max,
+            span,
+        } => {
+            out.push(TAG_VAR);
+            put_str(out, name);
+            put_option(out, *default_value, put_fixed);
+            put_option(out, *min, put_bound);
+            put_option(out, *max, put_bound);
+            put_option(out, *span, put_span);
+        }
+        Term::Number { value } => {
+            out.push(TAG_NUMBER);
+            put_fixed(out, *value);
+        }
+        Term::InfixExpr { op, left, right } => {
+            out.push(TAG_INFIX);
+            out.push(match op {
+                ArithOp::Add => 0,
+                ArithOp::Sub => 1,
+                ArithOp::Mul => 2,
+                ArithOp::Div => 3,
+            });
+            put_term(out, left);
+            put_term(out, right);
+        }
+        Term::Struct {
+            functor,
+            args,
+            span,
+        } => {
+            out.push(TAG_STRUCT);
+            put_str(out, functor);
+            put_terms(out, args);
+            put_option(out, *span, put_span);
+        }
+        Term::List { items, tail } => {
+            out.push(TAG_LIST);
+            put_terms(out, items);
+            put_option(out, tail.as_deref(), put_term);
+        }
+        Term::StringLit { value } => {
+            out.push(TAG_STRING);
+            put_str(out, value);
+        }
+        Term::Constraint { left, right } => {
+            out.push(TAG_CONSTRAINT);
+            put_term(out, left);
+            put_term(out, right);
+        }
+    }
+}
+
+fn put_clause(out: &mut Vec<u8>, clause: &Clause) {
+    match clause {
+        Clause::Fact(term) => {
+            out.push(TAG_FACT);
+            put_term(out, term);
+        }
+        Clause::Rule { head, body } => {
+            out.push(TAG_RULE);
+            put_term(out, head);
+            put_terms(out, body);
+        }
+        Clause::Use { path, expose, span } => {
+            out.push(TAG_USE);
+            put_str(out, path);
+            put_len(out, expose.len());
+            for name in expose {
+                put_str(out, name);
+            }
+            put_option(out, *span, put_span);
+        }
+    }
+}
+
+// ============================================================
+// デコード
+// ============================================================
+
+pub fn decode_clauses(bytes: &[u8]) -> Result<Vec<Clause>, DecodeError> {
+    let mut r = Reader { bytes, pos: 0 };
+    let version = r.u8()?;
+    if version != FORMAT_VERSION {
+        return Err(DecodeError::UnsupportedVersion(version));
+    }
+    let len = r.len()?;
+    let clauses = (0..len)
+        .map(|_| r.clause())
+        .collect::<Result<Vec<_>, _>>()?;
+    match bytes.len() - r.pos {
+        0 => Ok(clauses),
+        n => Err(DecodeError::TrailingBytes(n)),
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], DecodeError> {
+        let chunk = self
+            .bytes
+            .get(self.pos..self.pos + N)
+            .ok_or(DecodeError::UnexpectedEof)?;
+        self.pos += N;
+        Ok(chunk.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    fn len(&mut self) -> Result<usize, DecodeError> {
+        Ok(u32::from_le_bytes(self.take()?) as usize)
+    }
+
+    fn usize(&mut self) -> Result<usize, DecodeError> {
+        Ok(u64::from_le_bytes(self.take()?) as usize)
+    }
+
+    fn bool(&mut self) -> Result<bool, DecodeError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            tag => Err(DecodeError::InvalidTag { kind: "bool", tag }),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, DecodeError> {
+        let len = self.len()?;
+        let slice = self
+            .bytes
+            .get(self.pos..self.pos + len)
+            .ok_or(DecodeError::UnexpectedEof)?;
+        self.pos += len;
+        String::from_utf8(slice.to_vec()).map_err(|_| DecodeError::InvalidUtf8)
+    }
+
+    fn fixed(&mut self) -> Result<FixedPoint, DecodeError> {
+        Ok(FixedPoint::from_hundredths(i64::from_le_bytes(
+            self.take()?,
+        )))
+    }
+
+    fn option<T>(
+        &mut self,
+        read: impl FnOnce(&mut Self) -> Result<T, DecodeError>,
+    ) -> Result<Option<T>, DecodeError> {
+        if self.bool()? {
+            read(self).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn span(&mut self) -> Result<SrcSpan, DecodeError> {
+        Ok(SrcSpan {
+            start: self.usize()?,
+            end: self.usize()?,
+            file_id: u16::from_le_bytes(self.take()?),
+        })
+    }
+
+    fn bound(&mut self) -> Result<Bound, DecodeError> {
+        Ok(Bound {
+            value: self.fixed()?,
+            inclusive: self.bool()?,
+        })
+    }
+
+    fn terms(&mut self) -> Result<Vec<Term>, DecodeError> {
+        let len = self.len()?;
+        (0..len).map(|_| self.term()).collect()
+    }
+
+    fn term(&mut self) -> Result<Term, DecodeError> {
+        match self.u8()? {
+            TAG_VAR => Ok(Term::Var {
+                name: self.string()?,
+                scope: (),
+                default_value: self.option(Self::fixed)?,
+                min: self.option(Self::bound)?,
+                max: self.option(Self::bound)?,
+                span: self.option(Self::span)?,
+            }),
+            TAG_NUMBER => Ok(Term::Number {
+                value: self.fixed()?,
+            }),
+            TAG_INFIX => {
+                let op = match self.u8()? {
+                    0 => ArithOp::Add,
+                    1 => ArithOp::Sub,
+                    2 => ArithOp::Mul,
+                    3 => ArithOp::Div,
+                    tag => {
+                        return Err(DecodeError::InvalidTag {
+                            kind: "operator",
+                            tag,
+                        });
+                    }
+                };
+                Ok(Term::InfixExpr {
+                    op,
+                    left: Box::new(self.term()?),
+                    right: Box::new(self.term()?),
+                })
+            }
+            TAG_STRUCT => Ok(Term::Struct {
+                functor: self.string()?,
+                args: self.terms()?,
+                span: self.option(Self::span)?,
+            }),
+            TAG_LIST => Ok(Term::List {
+                items: self.terms()?,
+                tail: self.option(|r| r.term().map(Box::new))?,
+            }),
+            TAG_STRING => Ok(Term::StringLit {
+                value: self.string()?,
+            }),
+            TAG_CONSTRAINT => Ok(Term::Constraint {
+                left: Box::new(self.term()?),
+                right: Box::new(self.term()?),
+            }),
+            tag => Err(DecodeError::InvalidTag { kind: "term", tag }),
+        }
+    }
+
+    fn clause(&mut self) -> Result<Clause, DecodeError> {
+        match self.u8()? {
+            TAG_FACT => Ok(Clause::Fact(self.term()?)),
+            TAG_RULE => Ok(Clause::Rule {
+                head: self.term()?,
+                body: self.terms()?,
+            }),
+            TAG_USE => {
+                let path = self.string()?;
+                let len = self.len()?;
+                let expose = (0..len)
+                    .map(|_| self.string())
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(Clause::Use {
+                    path,
+                    expose,
+                    span: self.option(Self::span)?,
+                })
+            }
+            tag => Err(DecodeError::InvalidTag {
+                kind: "clause",
+                tag,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::{database, first_span};
+
+    const PROGRAM: &str = r#"
+        #use("bolts", expose([m5])).
+        size(10).
+        part(W@5, H) :- 0 < H <= 2.5, size(S), W = S * 2 - 1, cube(W, H, S / 4).
+        holes([1, 2 | T], "label") :- dif(T, []).
+        main :- part(3, 1) |> translate(_, 0, 0, 5) + sphere(1.25).
+    "#;
+
+    #[test]
+    fn test_round_trip_program() {
+        let clauses = database(PROGRAM).unwrap();
+        let decoded = decode_clauses(&encode_clauses(&clauses)).unwrap();
+        assert_eq!(decoded, clauses);
+    }
+
+    #[test]
+    fn test_round_trip_keeps_spans() {
+        let clauses = database(PROGRAM).unwrap();
+        let decoded = decode_clauses(&encode_clauses(&clauses)).unwrap();
+        for (a, b) in clauses.iter().zip(&decoded) {
+            if let (Clause::Rule { head: h1, .. }, Clause::Rule { head: h2, .. }) = (a, b) {
+                assert_eq!(first_span(h1), first_span(h2));
+            }
+        }
+    }
+
+    #[test]
+    fn test_decode_rejects_corrupt_input() {
+        let bytes = encode_clauses(&database(PROGRAM).unwrap());
+        assert_eq!(
+            decode_clauses(&bytes[..bytes.len() - 1]),
+            Err(DecodeError::UnexpectedEof)
+        );
+        assert_eq!(
+            decode_clauses(&[FORMAT_VERSION + 1]),
+            Err(DecodeError::UnsupportedVersion(FORMAT_VERSION + 1))
+        );
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert_eq!(
+            decode_clauses(&trailing),
+            Err(DecodeError::TrailingBytes(1))
+        );
+    }
+}
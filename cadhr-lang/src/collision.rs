@@ -1,6 +1,9 @@
-use crate::manifold_bridge::{ConversionError, Model3D};
+use crate::manifold_bridge::{ConversionError, Model3D, mesh_bounds, mesh_triangles};
+use crate::parse::{ERROR_TERM_DEPTH, FixedPoint, ScopedTerm, Term, number, struc};
+use crate::term_processor::{BuiltinPredicateResult, PredicateContext};
 use manifold_rs::{Manifold, Mesh};
 use std::path::PathBuf;
+use std::time::Instant;
 
 pub struct CollisionResult {
     pub combined_mesh: Mesh,
@@ -8,25 +11,6 @@ pub struct CollisionResult {
     pub part_count: usize,
 }
 
-fn compute_aabb(mesh: &Mesh) -> ([f64; 3], [f64; 3]) {
-    let verts = mesh.vertices();
-    let stride = mesh.num_props() as usize;
-    let mut aabb_min = [f64::INFINITY; 3];
-    let mut aabb_max = [f64::NEG_INFINITY; 3];
-    for chunk in verts.chunks(stride) {
-        for i in 0..3 {
-            let v = chunk[i] as f64;
-            if v < aabb_min[i] {
-                aabb_min[i] = v;
-            }
-            if v > aabb_max[i] {
-                aabb_max[i] = v;
-            }
-        }
-    }
-    (aabb_min, aabb_max)
-}
-
 fn aabb_overlap(a: &([f64; 3], [f64; 3]), b: &([f64; 3], [f64; 3])) -> bool {
     for i in 0..3 {
         if a.1[i] < b.0[i] || b.1[i] < a.0[i] {
@@ -59,14 +43,15 @@ pub fn check_collisions(
 
     let aabbs: Vec<_> = manifolds
         .iter()
-        .map(|m| compute_aabb(&m.to_mesh()))
+        .map(|m| mesh_bounds(&m.to_mesh()))
         .collect();
 
     let mut collision_meshes = Vec::new();
     for i in 0..manifolds.len() {
         for j in (i + 1)..manifolds.len() {
-            if !aabb_overlap(&aabbs[i], &aabbs[j]) {
-                continue;
+            match (&aabbs[i], &aabbs[j]) {
+                (Some(a), Some(b)) if aabb_overlap(a, b) => {}
+                _ => continue,
             }
             let intersection = manifolds[i].intersection(&manifolds[j]);
             if intersection.is_empty() {
@@ -86,6 +71,321 @@ pub fn check_collisions(
     })
 }
 
+// ============================================================
+// min_gap: 2形状間のすき間
+// ============================================================
+
+type Vec3 = [f64; 3];
+
+fn sub(a: Vec3, b: Vec3) -> Vec3 {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: Vec3, b: Vec3) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn lerp(a: Vec3, b: Vec3, t: f64) -> Vec3 {
+    [
+        a[0] + (b[0] - a[0]) * t,
+        a[1] + (b[1] - a[1]) * t,
+        a[2] + (b[2] - a[2]) * t,
+    ]
+}
+
+fn dist(a: Vec3, b: Vec3) -> f64 {
+    let d = sub(a, b);
+    dot(d, d).sqrt()
+}
+
+/// 三角形上の p への最近点（Ericson, Real-Time Collision Detection 5.1.5）
+fn closest_point_on_triangle(p: Vec3, a: Vec3, b: Vec3, c: Vec3) -> Vec3 {
+    let ab = sub(b, a);
+    let ac = sub(c, a);
+    let ap = sub(p, a);
+    let d1 = dot(ab, ap);
+    let d2 = dot(ac, ap);
+    if d1 <= 0.0 && d2 <= 0.0 {
+        return a;
+    }
+    let bp = sub(p, b);
+    let d3 = dot(ab, bp);
+    let d4 = dot(ac, bp);
+    if d3 >= 0.0 && d4 <= d3 {
+        return b;
+    }
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+        return lerp(a, b, d1 / (d1 - d3));
+    }
+    let cp = sub(p, c);
+    let d5 = dot(ab, cp);
+    let d6 = dot(ac, cp);
+    if d6 >= 0.0 && d5 <= d6 {
+        return c;
+    }
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+        return lerp(a, c, d2 / (d2 - d6));
+    }
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0.0 && (d4 - d3) >= 0.0 && (d5 - d6) >= 0.0 {
+        return lerp(b, c, (d4 - d3) / ((d4 - d3) + (d5 - d6)));
+    }
+    let denom = 1.0 / (va + vb + vc);
+    let v = vb * denom;
+    let w = vc * denom;
+    [
+        a[0] + ab[0] * v + ac[0] * w,
+        a[1] + ab[1] * v + ac[1] * w,
+        a[2] + ab[2] * v + ac[2] * w,
+    ]
+}
+
+/// 線分同士の最短距離（Ericson 5.1.9）
+fn segment_distance(p1: Vec3, q1: Vec3, p2: Vec3, q2: Vec3) -> f64 {
+    let d1 = sub(q1, p1);
+    let d2 = sub(q2, p2);
+    let r = sub(p1, p2);
+    let a = dot(d1, d1);
+    let e = dot(d2, d2);
+    let f = dot(d2, r);
+    let (s, t) = if a <= f64::EPSILON && e <= f64::EPSILON {
+        (0.0, 0.0)
+    } else if a <= f64::EPSILON {
+        (0.0, (f / e).clamp(0.0, 1.0))
+    } else {
+        let c = dot(d1, r);
+        if e <= f64::EPSILON {
+            ((-c / a).clamp(0.0, 1.0), 0.0)
+        } else {
+            let b = dot(d1, d2);
+            let denom = a * e - b * b;
+            let mut s = if denom > 0.0 {
+                ((b * f - c * e) / denom).clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+            let mut t = (b * s + f) / e;
+            if t < 0.0 {
+                t = 0.0;
+                s = (-c / a).clamp(0.0, 1.0);
+            } else if t > 1.0 {
+                t = 1.0;
+                s = ((b - c) / a).clamp(0.0, 1.0);
+            }
+            (s, t)
+        }
+    };
+    dist(lerp(p1, q1, s), lerp(p2, q2, t))
+}
+
+fn aabb_distance(a: &(Vec3, Vec3), b: &(Vec3, Vec3)) -> f64 {
+    let gap: Vec3 = std::array::from_fn(|i| (b.0[i] - a.1[i]).max(a.0[i] - b.1[i]).max(0.0));
+    dot(gap, gap).sqrt()
+}
+
+fn triangle_aabb(t: &[Vec3; 3]) -> (Vec3, Vec3) {
+    let min = std::array::from_fn(|i| t[0][i].min(t[1][i]).min(t[2][i]));
+    let max = std::array::from_fn(|i| t[0][i].max(t[1][i]).max(t[2][i]));
+    (min, max)
+}
+
+fn triangle_distance(ta: &[Vec3; 3], tb: &[Vec3; 3]) -> f64 {
+    let mut best = f64::INFINITY;
+    for &p in ta {
+        best = best.min(dist(p, closest_point_on_triangle(p, tb[0], tb[1], tb[2])));
+    }
+    for &p in tb {
+        best = best.min(dist(p, closest_point_on_triangle(p, ta[0], ta[1], ta[2])));
+    }
+    for i in 0..3 {
+        for j in 0..3 {
+            best = best.min(segment_distance(
+                ta[i],
+                ta[(i + 1) % 3],
+                tb[j],
+                tb[(j + 1) % 3],
+            ));
+        }
+    }
+    best
+}
+
+/// 期限を確認する間隔（A 側の三角形の数）
+const MIN_GAP_DEADLINE_CHECK_INTERVAL: usize = 256;
+
+/// 三角形集合同士の最短距離（search_len で頭打ち）。その時点の最短距離より箱同士が離れている
+/// 三角形対は距離を計算しない。deadline を過ぎたら None
+fn min_triangle_gap(
+    tris_a: &[[Vec3; 3]],
+    tris_b: &[[Vec3; 3]],
+    search_len: f64,
+    deadline: Option<Instant>,
+) -> Option<f64> {
+    let boxes_b: Vec<(Vec3, Vec3)> = tris_b.iter().map(triangle_aabb).collect();
+    let mut best = search_len;
+    for (n, ta) in tris_a.iter().enumerate() {
+        if n % MIN_GAP_DEADLINE_CHECK_INTERVAL == 0 && deadline.is_some_and(|d| Instant::now() >= d)
+        {
+            return None;
+        }
+        let box_a = triangle_aabb(ta);
+        for (tb, box_b) in tris_b.iter().zip(&boxes_b) {
+            if aabb_distance(&box_a, box_b) < best {
+                best = best.min(triangle_distance(ta, tb));
+            }
+        }
+    }
+    Some(best)
+}
+
+/// 2形状の表面間の最短距離。重なっていれば0、search_len より離れていれば search_len を返す。
+/// 使っている manifold-rs には MinGap の binding がないため、三角形対の距離から求める。
+/// deadline を過ぎたら None
+pub fn min_gap(
+    a: &Manifold,
+    b: &Manifold,
+    search_len: f64,
+    deadline: Option<Instant>,
+) -> Option<f64> {
+    if !a.intersection(b).is_empty() {
+        return Some(0.0);
+    }
+    let (mesh_a, mesh_b) = (a.to_mesh(), b.to_mesh());
+    let (Some(box_a), Some(box_b)) = (mesh_bounds(&mesh_a), mesh_bounds(&mesh_b)) else {
+        return Some(search_len);
+    };
+    if aabb_distance(&box_a, &box_b) >= search_len {
+        return Some(search_len);
+    }
+    // B の箱から search_len 以上離れた A の三角形は候補にならない
+    let tris_a: Vec<[Vec3; 3]> = mesh_triangles(&mesh_a)
+        .into_iter()
+        .filter(|t| aabb_distance(&triangle_aabb(t), &box_b) < search_len)
+        .collect();
+    min_triangle_gap(&tris_a, &mesh_triangles(&mesh_b), search_len, deadline)
+}
+
+/// min_gap(ShapeA, ShapeB, SearchLen, Gap)
+fn eval_min_gap(args: &[ScopedTerm], ctx: &PredicateContext) -> BuiltinPredicateResult {
+    let evaluate = |term: &ScopedTerm| {
+        Model3D::from_term(term)
+            .and_then(|m| m.evaluate(ctx.include_paths))
+            .map_err(|e| e.to_string())
+    };
+    let a = evaluate(&args[0])?;
+    let b = evaluate(&args[1])?;
+    let search_len = match &args[2] {
+        Term::Number { value } => value.to_f64(),
+        other => {
+            return Err(format!(
+                "min_gap: search length must be a number, got {}",
+                other.elided(ERROR_TERM_DEPTH)
+            ));
+        }
+    };
+    let gap = min_gap(&a, &b, search_len, ctx.deadline).ok_or("min_gap: evaluation timed out")?;
+    Ok(vec![(3, number(FixedPoint::from_f64(gap)))])
+}
+
+inventory::submit! {
+    crate::term_processor::BuiltinPredicate {
+        name: "min_gap",
+        arity: 4,
+        resolve_args: true,
+        eval: eval_min_gap,
+    }
+}
+
+/// 足元 (XY) の矩形 [幅, 奥行き] を shelf packing で並べ、各矩形の左下の位置を返す。
+/// 棚の幅は全体がおおよそ正方形になるように決める
+fn shelf_pack(footprints: &[[f64; 2]], spacing: f64) -> Vec<[f64; 2]> {
+    let area: f64 = footprints
+        .iter()
+        .map(|[w, d]| (w + spacing) * (d + spacing))
+        .sum();
+    let widest = footprints.iter().map(|[w, _]| *w).fold(0.0, f64::max);
+    let shelf_width = widest.max(area.sqrt());
+
+    let mut order: Vec<usize> = (0..footprints.len()).collect();
+    order.sort_by(|&a, &b| footprints[b][1].total_cmp(&footprints[a][1]));
+
+    let mut positions = vec![[0.0; 2]; footprints.len()];
+    let (mut x, mut y, mut shelf_depth) = (0.0, 0.0, 0.0_f64);
+    for i in order {
+        let [w, d] = footprints[i];
+        if x > 0.0 && x + w > shelf_width {
+            y += shelf_depth + spacing;
+            x = 0.0;
+            shelf_depth = 0.0;
+        }
+        positions[i] = [x, y];
+        x += w + spacing;
+        shelf_depth = shelf_depth.max(d);
+    }
+    positions
+}
+
+/// arrange(Shapes, Spacing, Arranged): 各形状を Spacing 空けて XY 平面上に並べ、底面を z=0 に揃えた和を返す
+fn eval_arrange(args: &[ScopedTerm], ctx: &PredicateContext) -> BuiltinPredicateResult {
+    let Term::List { items, tail: None } = &args[0] else {
+        return Err(format!(
+            "arrange: argument 0 must be a proper list of shapes, got {}",
+            args[0].elided(ERROR_TERM_DEPTH)
+        ));
+    };
+    let spacing = match &args[1] {
+        Term::Number { value } => value.to_f64(),
+        other => {
+            return Err(format!(
+                "arrange: spacing must be a number, got {}",
+                other.elided(ERROR_TERM_DEPTH)
+            ));
+        }
+    };
+    let aabbs = items
+        .iter()
+        .map(|item| {
+            Model3D::from_term(item)
+                .and_then(|m| m.evaluate(ctx.include_paths))
+                .map(|m| mesh_bounds(&m.to_mesh()).unwrap_or_default())
+                .map_err(|e| e.to_string())
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let footprints: Vec<[f64; 2]> = aabbs
+        .iter()
+        .map(|(min, max)| [max[0] - min[0], max[1] - min[1]])
+        .collect();
+    let positions = shelf_pack(&footprints, spacing);
+
+    let placed: Vec<ScopedTerm> = items
+        .iter()
+        .zip(aabbs.iter().zip(positions))
+        .map(|(item, ((min, _), [x, y]))| {
+            let offset = [x - min[0], y - min[1], -min[2]];
+            let mut translate_args = vec![item.clone()];
+            translate_args.extend(offset.map(|v| number(FixedPoint::from_f64(v))));
+            struc("translate".to_string(), translate_args)
+        })
+        .collect();
+    let arranged = placed
+        .into_iter()
+        .rev()
+        .reduce(|acc, part| struc("union".to_string(), vec![part, acc]))
+        .unwrap_or_else(|| struc("empty".to_string(), vec![]));
+    Ok(vec![(2, arranged)])
+}
+
+inventory::submit! {
+    crate::term_processor::BuiltinPredicate {
+        name: "arrange",
+        arity: 3,
+        resolve_args: true,
+        eval: eval_arrange,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -141,4 +441,113 @@ mod tests {
             "separated cubes should have no collision"
         );
     }
+
+    #[test]
+    fn test_min_gap_between_separated_and_overlapping_cubes() {
+        use crate::test_util::solution;
+
+        assert_eq!(
+            solution(
+                "",
+                "min_gap(cube(10, 10, 10), translate(cube(10, 10, 10), 15, 0, 0), 100, G)."
+            ),
+            "G = 5"
+        );
+        assert_eq!(
+            solution(
+                "",
+                "min_gap(cube(10, 10, 10), translate(cube(10, 10, 10), 5, 0, 0), 100, G)."
+            ),
+            "G = 0"
+        );
+        assert_eq!(
+            solution(
+                "",
+                "min_gap(cube(10, 10, 10), translate(cube(10, 10, 10), 50, 0, 0), 20, G)."
+            ),
+            "G = 20"
+        );
+    }
+
+    #[test]
+    fn test_min_triangle_gap_finds_nearest_pair() {
+        let tri = |x: f64| [[x, 0.0, 0.0], [x, 1.0, 0.0], [x, 0.0, 1.0]];
+        let near = [tri(0.0), tri(-50.0)];
+        let far = [tri(40.0), tri(3.0)];
+        assert_eq!(min_triangle_gap(&near, &far, 100.0, None), Some(3.0));
+        assert_eq!(min_triangle_gap(&near, &far, 2.0, None), Some(2.0));
+    }
+
+    #[test]
+    fn test_min_triangle_gap_stops_at_deadline() {
+        let tri = [[0.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+        assert_eq!(
+            min_triangle_gap(&[tri], &[tri], 1.0, Some(Instant::now())),
+            None
+        );
+    }
+
+    #[test]
+    fn test_shelf_pack_keeps_spacing() {
+        let footprints = [[10.0, 10.0], [20.0, 5.0], [5.0, 30.0], [8.0, 8.0]];
+        let spacing = 2.0;
+        let positions = shelf_pack(&footprints, spacing);
+        for i in 0..footprints.len() {
+            for j in (i + 1)..footprints.len() {
+                let apart = (0..2).any(|k| {
+                    positions[i][k] + footprints[i][k] + spacing <= positions[j][k]
+                        || positions[j][k] + footprints[j][k] + spacing <= positions[i][k]
+                });
+                assert!(apart, "{:?} and {:?} overlap", i, j);
+            }
+        }
+    }
+
+    #[test]
+    fn test_arranged_cubes_do_not_overlap() {
+        use crate::test_util::{binding, run_query};
+
+        let (_, env) = run_query(
+            "",
+            "arrange([cube(10, 10, 10), cube(20, 5, 5), translate(cube(5, 5, 30), 3, 3, 3)], 2, A).",
+        );
+        let mut parts = Vec::new();
+        let mut rest = binding(&env, "A");
+        while let Term::Struct { functor, args, .. } = &rest
+            && functor == "union"
+        {
+            parts.push(args[0].clone());
+            rest = args[1].clone();
+        }
+        parts.push(rest);
+        assert_eq!(parts.len(), 3);
+
+        let manifolds: Vec<Manifold> = parts
+            .iter()
+            .map(|p| Model3D::from_term(p).unwrap().evaluate(&[]).unwrap())
+            .collect();
+        for i in 0..manifolds.len() {
+            for j in (i + 1)..manifolds.len() {
+                assert!(min_gap(&manifolds[i], &manifolds[j], 100.0, None).unwrap() > 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_segment_distance_between_skew_edges() {
+        let d = segment_distance(
+            [0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [0.5, -1.0, 2.0],
+            [0.5, 1.0, 2.0],
+        );
+        assert!((d - 2.0).abs() < 1e-12);
+        let p = closest_point_on_triangle(
+            [0.2, 0.2, 3.0],
+            [0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+        );
+        assert!(dist(p, [0.2, 0.2, 0.0]) < 1e-12);
+    }
 }
@@ -106,7 +106,7 @@ fn try_eval(expr: &ArithExpr) -> Option<FixedPoint> {
                 ArithOp::Add => l + r,
                 ArithOp::Sub => l - r,
                 ArithOp::Mul => l * r,
-                ArithOp::Div => l / r,
+                ArithOp::Div => l.checked_div(r)?,
             })
         }
         _ => None,
@@ -140,7 +140,7 @@ fn try_solve_for_var(expr: &ArithExpr, target: FixedPoint) -> Option<(String, Fi
                                 return None;
                             }
                             let candidate = l_val / target;
-                            if l_val / candidate != target {
+                            if l_val.checked_div(candidate) != Some(target) {
                                 return None;
                             }
                             candidate
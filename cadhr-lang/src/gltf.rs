@@ -0,0 +1,194 @@
+//! Web プレビュー用の glTF 2.0 バイナリ (.glb) 書き出し。メッシュ1つ・ノード1つだけの最小構成
+
+use manifold_rs::Mesh;
+
+const GLB_MAGIC: u32 = 0x4654_6C67; // "glTF"
+const GLB_VERSION: u32 = 2;
+const CHUNK_JSON: u32 = 0x4E4F_534A; // "JSON"
+const CHUNK_BIN: u32 = 0x004E_4942; // "BIN\0"
+
+const FLOAT: u32 = 5126;
+const UNSIGNED_INT: u32 = 5125;
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const TRIANGLES: u32 = 4;
+
+/// manifold-rs のメッシュを .glb にする。calculate_normals 済み（num_props >= 6）なら NORMAL も書く
+pub fn mesh_to_glb(mesh: &Mesh) -> Vec<u8> {
+    let stride = mesh.num_props() as usize;
+    let vertices = mesh.vertices();
+    let positions: Vec<[f32; 3]> = vertices
+        .chunks_exact(stride)
+        .map(|c| [c[0], c[1], c[2]])
+        .collect();
+    let normals: Option<Vec<[f32; 3]>> = (stride >= 6).then(|| {
+        vertices
+            .chunks_exact(stride)
+            .map(|c| [c[3], c[4], c[5]])
+            .collect()
+    });
+    encode_glb(&positions, normals.as_deref(), &mesh.indices())
+}
+
+fn encode_glb(positions: &[[f32; 3]], normals: Option<&[[f32; 3]]>, indices: &[u32]) -> Vec<u8> {
+    let mut bin = Vec::new();
+    let mut views = Vec::new();
+    let mut push_view = |bin: &mut Vec<u8>, data: Vec<u8>, target: u32| {
+        views.push(serde_json::json!({
+            "buffer": 0,
+            "byteOffset": bin.len(),
+            "byteLength": data.len(),
+            "target": target,
+        }));
+        bin.extend(data);
+    };
+    let vec3_bytes = |vs: &[[f32; 3]]| -> Vec<u8> {
+        vs.iter().flatten().flat_map(|v| v.to_le_bytes()).collect()
+    };
+
+    push_view(&mut bin, vec3_bytes(positions), ARRAY_BUFFER);
+    if let Some(normals) = normals {
+        push_view(&mut bin, vec3_bytes(normals), ARRAY_BUFFER);
+    }
+    let index_bytes = indices.iter().flat_map(|i| i.to_le_bytes()).collect();
+    push_view(&mut bin, index_bytes, ELEMENT_ARRAY_BUFFER);
+
+    let mut min = [f32::INFINITY; 3];
+    let mut max = [f32::NEG_INFINITY; 3];
+    for p in positions {
+        for i in 0..3 {
+            min[i] = min[i].min(p[i]);
+            max[i] = max[i].max(p[i]);
+        }
+    }
+    if positions.is_empty() {
+        (min, max) = ([0.0; 3], [0.0; 3]);
+    }
+
+    let mut accessors = vec![serde_json::json!({
+        "bufferView": 0,
+        "componentType": FLOAT,
+        "count": positions.len(),
+        "type": "VEC3",
+        "min": min,
+        "max": max,
+    })];
+    let mut attributes = serde_json::json!({ "POSITION": 0 });
+    if normals.is_some() {
+        attributes["NORMAL"] = serde_json::json!(accessors.len());
+        accessors.push(serde_json::json!({
+            "bufferView": accessors.len(),
+            "componentType": FLOAT,
+            "count": positions.len(),
+            "type": "VEC3",
+        }));
+    }
+    let indices_accessor = accessors.len();
+    accessors.push(serde_json::json!({
+        "bufferView": indices_accessor,
+        "componentType": UNSIGNED_INT,
+        "count": indices.len(),
+        "type": "SCALAR",
+    }));
+
+    let gltf = serde_json::json!({
+        "asset": { "version": "2.0", "generator": "cadhr" },
+        "scene": 0,
+        "scenes": [{ "nodes": [0] }],
+        "nodes": [{ "mesh": 0 }],
+        "meshes": [{
+            "primitives": [{
+                "attributes": attributes,
+                "indices": indices_accessor,
+                "mode": TRIANGLES,
+            }],
+        }],
+        "buffers": [{ "byteLength": bin.len() }],
+        "bufferViews": views,
+        "accessors": accessors,
+    });
+
+    let mut json = serde_json::to_vec(&gltf).unwrap();
+    pad_to_4(&mut json, b' ');
+    pad_to_4(&mut bin, 0);
+
+    let total = 12 + 8 + json.len() + 8 + bin.len();
+    let mut glb = Vec::with_capacity(total);
+    for word in [GLB_MAGIC, GLB_VERSION, total as u32] {
+        glb.extend(word.to_le_bytes());
+    }
+    for (chunk_type, data) in [(CHUNK_JSON, &json), (CHUNK_BIN, &bin)] {
+        glb.extend((data.len() as u32).to_le_bytes());
+        glb.extend(chunk_type.to_le_bytes());
+        glb.extend(data);
+    }
+    glb
+}
+
+fn pad_to_4(data: &mut Vec<u8>, fill: u8) {
+    data.resize(data.len().next_multiple_of(4), fill);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_u32(data: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+    }
+
+    /// (JSON, BIN) のチャンクを取り出しつつヘッダを検証する
+    fn split_chunks(glb: &[u8]) -> (serde_json::Value, &[u8]) {
+        assert_eq!(read_u32(glb, 0), GLB_MAGIC);
+        assert_eq!(read_u32(glb, 4), 2);
+        assert_eq!(read_u32(glb, 8) as usize, glb.len());
+        let json_len = read_u32(glb, 12) as usize;
+        assert_eq!(read_u32(glb, 16), CHUNK_JSON);
+        assert_eq!(json_len % 4, 0);
+        let json = serde_json::from_slice(&glb[20..20 + json_len]).unwrap();
+        let bin_start = 20 + json_len;
+        let bin_len = read_u32(glb, bin_start) as usize;
+        assert_eq!(read_u32(glb, bin_start + 4), CHUNK_BIN);
+        assert_eq!(bin_start + 8 + bin_len, glb.len());
+        (json, &glb[bin_start + 8..])
+    }
+
+    #[test]
+    fn test_encode_glb_layout() {
+        let positions = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 2.0, 0.0]];
+        let normals = [[0.0, 0.0, 1.0]; 3];
+        let glb = encode_glb(&positions, Some(&normals), &[0, 1, 2]);
+        let (json, bin) = split_chunks(&glb);
+
+        assert_eq!(bin.len(), 36 + 36 + 12);
+        assert_eq!(json["buffers"][0]["byteLength"], 84);
+        let primitive = &json["meshes"][0]["primitives"][0];
+        assert_eq!(primitive["attributes"]["NORMAL"], 1);
+        assert_eq!(primitive["indices"], 2);
+        assert_eq!(
+            json["accessors"][0]["max"],
+            serde_json::json!([1.0, 2.0, 0.0])
+        );
+        assert_eq!(json["bufferViews"][2]["byteOffset"], 72);
+        assert_eq!(read_u32(bin, 72 + 8), 2);
+    }
+
+    #[test]
+    fn test_encode_glb_without_normals() {
+        let glb = encode_glb(&[[0.0; 3]; 3], None, &[0, 1, 2]);
+        let (json, _) = split_chunks(&glb);
+        let primitive = &json["meshes"][0]["primitives"][0];
+        assert!(primitive["attributes"].get("NORMAL").is_none());
+        assert_eq!(primitive["indices"], 1);
+    }
+
+    #[test]
+    fn test_cube_mesh_to_glb() {
+        let mesh = manifold_rs::Manifold::cube(1.0, 1.0, 1.0)
+            .calculate_normals(0, 30.0)
+            .to_mesh();
+        let glb = mesh_to_glb(&mesh);
+        let (json, _) = split_chunks(&glb);
+        assert_eq!(json["accessors"][2]["count"], mesh.indices().len());
+    }
+}
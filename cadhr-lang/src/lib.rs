@@ -1,10 +1,16 @@
 pub mod bezier;
 pub mod bom;
+pub mod builtin_predicates;
+pub mod codec;
 pub mod collision;
 pub mod constraint;
+pub mod gltf;
 pub mod manifold_bridge;
 pub mod module;
+pub mod param_sweep;
 pub mod parse;
 pub mod sweep;
 pub mod term_processor;
 pub mod term_rewrite;
+#[cfg(test)]
+mod test_util;
@@ -3,11 +3,19 @@
 //! Term（書き換え後の項）を Model3D / Model2D 中間表現に変換し、
 //! それを manifold-rs の Manifold オブジェクトに評価する。
 
-use crate::parse::{ArithOp, FixedPoint, SrcSpan, Term, term_as_fixed_point};
+use crate::parse::{
+    ArithOp, ERROR_TERM_DEPTH, FixedPoint, ScopedTerm, SrcSpan, Term, first_span, list, number,
+    number_int, struc, term_as_fixed_point,
+};
 use manifold_rs::{Manifold, Mesh};
+use std::cell::RefCell;
 use std::fmt;
+use std::hash::{DefaultHasher, Hash, Hasher};
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant, SystemTime};
 
 #[derive(Debug, Clone, Copy)]
 pub struct TrackedF64 {
@@ -38,14 +46,19 @@ pub enum Model3D {
         y: f64,
         z: f64,
     },
+    /// segments が None なら評価時の分割数を使う
     Sphere {
         radius: f64,
+        segments: Option<u32>,
     },
     Cylinder {
         radius: f64,
         height: f64,
+        segments: Option<u32>,
     },
     Tetrahedron,
+    /// 空の形状。union の単位元
+    Empty,
     Union(Box<Model3D>, Box<Model3D>),
     Difference(Box<Model3D>, Box<Model3D>),
     Intersection(Box<Model3D>, Box<Model3D>),
@@ -68,6 +81,11 @@ pub enum Model3D {
         y: f64,
         z: f64,
     },
+    /// 各頂点を (x, y, z) の式で移す
+    Warp {
+        model: Box<Model3D>,
+        exprs: [WarpExpr; 3],
+    },
     LinearExtrude {
         profile: Model2D,
         height: f64,
@@ -82,16 +100,369 @@ pub enum Model3D {
     Revolve {
         profile: Model2D,
         degrees: f64,
+        segments: Option<u32>,
     },
     Stl {
         path: String,
     },
     SweepExtrude {
-        profile_data: Vec<(f64, f64)>,
-        path_data: Vec<(f64, f64)>,
+        profile: Model2D,
+        path: Model2D,
+    },
+    Shared(Arc<SharedShape>),
+    WithTolerance {
+        model: Box<Model3D>,
+        tolerance: f64,
+    },
+    WithSegments {
+        model: Box<Model3D>,
+        segments: u32,
+    },
+    /// decompose が返す連結成分。番号が decompose と食い違わないよう、model は常に既定の分割数・
+    /// tolerance なしで評価する
+    Component {
+        model: Box<Model3D>,
+        index: usize,
+    },
+    /// バウンディングボックスの中心を原点に移す。箱を求めるため、translate などと違い
+    /// 子を評価してからでないと移動量が決まらない
+    Center {
+        model: Box<Model3D>,
     },
 }
 
+/// warp の座標式。x, y, z は変形前の頂点座標を表す
+#[derive(Debug, Clone)]
+pub enum WarpExpr {
+    Num(f64),
+    Coord(usize),
+    BinOp(ArithOp, Box<WarpExpr>, Box<WarpExpr>),
+}
+
+impl WarpExpr {
+    fn eval(&self, p: [f64; 3]) -> f64 {
+        match self {
+            WarpExpr::Num(v) => *v,
+            WarpExpr::Coord(i) => p[*i],
+            WarpExpr::BinOp(op, l, r) => {
+                let (l, r) = (l.eval(p), r.eval(p));
+                match op {
+                    ArithOp::Add => l + r,
+                    ArithOp::Sub => l - r,
+                    ArithOp::Mul => l * r,
+                    ArithOp::Div => l / r,
+                }
+            }
+        }
+    }
+}
+
+/// let_shape で名前を付けた形状。参照が複数あっても同じ分割数・tolerance では評価は一度だけで、
+/// 結果のメッシュを使い回す。
+#[derive(Debug)]
+pub struct SharedShape {
+    pub name: String,
+    pub model: Model3D,
+    /// (segments, tolerance のビット列) ごとのメッシュ
+    mesh_cache: Mutex<std::collections::HashMap<(u32, Option<u64>), SharedMesh>>,
+    evaluations: AtomicUsize,
+}
+
+type SharedMesh = Arc<(Vec<f32>, Vec<u32>)>;
+
+impl SharedShape {
+    fn new(name: String, model: Model3D) -> Self {
+        Self {
+            name,
+            model,
+            mesh_cache: Mutex::default(),
+            evaluations: AtomicUsize::new(0),
+        }
+    }
+
+    /// model を実際に評価した回数
+    pub fn evaluation_count(&self) -> usize {
+        self.evaluations.load(Ordering::Relaxed)
+    }
+
+    fn evaluate(&self, ctx: &EvalContext) -> Result<Manifold, ConversionError> {
+        let key = (ctx.segments, ctx.tolerance.map(f64::to_bits));
+        let cached = self.mesh_cache().get(&key).cloned();
+        let shared = match cached {
+            Some(shared) => shared,
+            None => {
+                self.evaluations.fetch_add(1, Ordering::Relaxed);
+                let mesh = self.model.evaluate_in(ctx)?.to_mesh();
+                let shared = Arc::new((mesh_positions(&mesh), mesh.indices()));
+                self.mesh_cache().insert(key, shared.clone());
+                shared
+            }
+        };
+        let (verts, indices) = shared.as_ref();
+        Ok(Manifold::from_mesh(Mesh::new(verts, indices)))
+    }
+
+    fn mesh_cache(
+        &self,
+    ) -> MutexGuard<'_, std::collections::HashMap<(u32, Option<u64>), SharedMesh>> {
+        self.mesh_cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+type ShapeBindings = [(String, Arc<SharedShape>)];
+
+/// 頂点属性から xyz だけを取り出す
+pub(crate) fn mesh_positions(mesh: &Mesh) -> Vec<f32> {
+    let num_props = mesh.num_props() as usize;
+    mesh.vertices()
+        .chunks(num_props)
+        .flat_map(|chunk| [chunk[0], chunk[1], chunk[2]])
+        .collect()
+}
+
+/// mesh_positions の座標列の (最小, 最大)。頂点がなければ None
+fn position_bounds(positions: &[f32]) -> Option<([f64; 3], [f64; 3])> {
+    if positions.is_empty() {
+        return None;
+    }
+    let mut min = [f64::INFINITY; 3];
+    let mut max = [f64::NEG_INFINITY; 3];
+    for p in positions.chunks_exact(3) {
+        for i in 0..3 {
+            min[i] = min[i].min(p[i] as f64);
+            max[i] = max[i].max(p[i] as f64);
+        }
+    }
+    Some((min, max))
+}
+
+/// メッシュの頂点座標の (最小, 最大)。頂点がなければ None
+pub(crate) fn mesh_bounds(mesh: &Mesh) -> Option<([f64; 3], [f64; 3])> {
+    position_bounds(&mesh_positions(mesh))
+}
+
+/// 三角形ごとの頂点座標
+pub(crate) fn mesh_triangles(mesh: &Mesh) -> Vec<[[f64; 3]; 3]> {
+    let positions = mesh_positions(mesh);
+    let vertex = |i: u32| std::array::from_fn(|k| positions[i as usize * 3 + k] as f64);
+    mesh.indices()
+        .chunks_exact(3)
+        .map(|t| [vertex(t[0]), vertex(t[1]), vertex(t[2])])
+        .collect()
+}
+
+/// これより小さい絶対値の scale 倍率は 0 とみなす
+const MIN_SCALE_FACTOR: f64 = 1e-9;
+
+/// 1 軸だけを -1 倍し、残りを 1 倍する鏡映か
+fn is_unit_reflection(factors: [f64; 3]) -> bool {
+    factors.iter().filter(|&&f| f == -1.0).count() == 1 && factors.iter().all(|&f| f.abs() == 1.0)
+}
+
+/// 評価中に部分木へ引き継ぐ設定。with_tolerance / with_segments は上書きした複製を子に渡す。
+#[derive(Clone, Copy)]
+struct EvalContext<'a> {
+    include_paths: &'a [PathBuf],
+    tolerance: Option<f64>,
+    /// 分割数を明示しないプリミティブの分割数
+    segments: u32,
+    profile: Option<&'a EvalProfile>,
+    cache: &'a EvalCache,
+}
+
+/// 形状の連結成分ごとの頂点座標とインデックス
+type ComponentParts = Arc<Vec<(Vec<f32>, Vec<u32>)>>;
+
+/// 一回の評価の間だけ使い回す中間結果
+#[derive(Default)]
+pub struct EvalCache {
+    /// component の元形状の structural_hash ごとの連結成分
+    components: RefCell<std::collections::HashMap<u64, ComponentParts>>,
+    stl: Arc<StlCache>,
+}
+
+impl EvalCache {
+    /// stl の読み込み結果だけは、評価をまたいで生きる stl と共有する
+    pub fn with_stl_cache(stl: Arc<StlCache>) -> Self {
+        Self {
+            components: Default::default(),
+            stl,
+        }
+    }
+
+    /// model の連結成分。同じ形状を参照する component が並んでも評価と分解は一度で済む
+    fn components(
+        &self,
+        model: &Model3D,
+        ctx: &EvalContext,
+    ) -> Result<ComponentParts, ConversionError> {
+        let key = model.structural_hash(ctx.include_paths);
+        if let Some(parts) = self.components.borrow().get(&key) {
+            return Ok(parts.clone());
+        }
+        let mesh = model.evaluate_in(ctx)?.to_mesh();
+        let parts = Arc::new(split_connected_components(
+            &mesh_positions(&mesh),
+            &mesh.indices(),
+        ));
+        self.components.borrow_mut().insert(key, parts.clone());
+        Ok(parts)
+    }
+}
+
+/// evaluate_profiled で集めるノードごとの評価時間。子の評価時間を含み、子が先に並ぶ
+#[derive(Debug, Default)]
+pub struct EvalProfile {
+    entries: RefCell<Vec<(String, Duration)>>,
+}
+
+impl EvalProfile {
+    fn record(&self, label: &str, elapsed: Duration) {
+        self.entries.borrow_mut().push((label.to_string(), elapsed));
+    }
+
+    pub fn take_profile(&self) -> Vec<(String, Duration)> {
+        self.entries.take()
+    }
+}
+
+/// 同じ結合的演算が連なる部分木を平坦化し、被演算子を左から順に返す
+fn flatten_chain(
+    model: &Model3D,
+    split: fn(&Model3D) -> Option<(&Model3D, &Model3D)>,
+) -> Vec<&Model3D> {
+    match split(model) {
+        Some((a, b)) => {
+            let mut operands = flatten_chain(a, split);
+            operands.extend(flatten_chain(b, split));
+            operands
+        }
+        None => vec![model],
+    }
+}
+
+/// 隣り合う組ごとに畳み込み、中間形状が片側に偏って大きくならないようにする
+fn reduce_balanced(
+    mut manifolds: Vec<Manifold>,
+    op: impl Fn(&Manifold, &Manifold) -> Manifold,
+) -> Manifold {
+    while manifolds.len() > 1 {
+        let mut next = Vec::with_capacity(manifolds.len().div_ceil(2));
+        let mut iter = manifolds.into_iter();
+        while let Some(a) = iter.next() {
+            next.push(match iter.next() {
+                Some(b) => op(&a, &b),
+                None => a,
+            });
+        }
+        manifolds = next;
+    }
+    manifolds.pop().unwrap_or_else(Manifold::empty)
+}
+
+/// STL から読み込んだ頂点座標とインデックス
+type StlMeshData = Arc<(Vec<f32>, Vec<u32>)>;
+
+/// StlCache が保持するファイル数の上限
+const STL_CACHE_CAPACITY: usize = 16;
+
+/// パスごとに最終更新時刻と読み込み結果を保持し、ファイルが変わっていなければ再パースしない。
+/// 上限を超えると最も長く使われていないものから捨てる
+#[derive(Default)]
+pub struct StlCache {
+    /// 最近使ったものほど後ろに並ぶ
+    entries: Mutex<Vec<(PathBuf, SystemTime, StlMeshData)>>,
+}
+
+impl StlCache {
+    fn entries(&self) -> MutexGuard<'_, Vec<(PathBuf, SystemTime, StlMeshData)>> {
+        // 保持しているのは読み込み済みのデータだけなので、他のスレッドが panic しても使い続けられる
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn load(&self, path: &Path) -> Result<StlMeshData, ConversionError> {
+        let io_error = |e: std::io::Error| ConversionError::IoError {
+            functor: "stl".into(),
+            message: format!("{}: {}", path.display(), e),
+        };
+        let mtime = std::fs::metadata(path)
+            .and_then(|m| m.modified())
+            .map_err(io_error)?;
+        {
+            let mut entries = self.entries();
+            if let Some(i) = entries
+                .iter()
+                .position(|(p, t, _)| p == path && *t == mtime)
+            {
+                let entry = entries.remove(i);
+                let data = entry.2.clone();
+                entries.push(entry);
+                return Ok(data);
+            }
+        }
+
+        let data = read_stl_mesh(path).map_err(io_error)?;
+        let mut entries = self.entries();
+        entries.retain(|(p, _, _)| p != path);
+        if entries.len() >= STL_CACHE_CAPACITY {
+            entries.remove(0);
+        }
+        entries.push((path.to_path_buf(), mtime, data.clone()));
+        Ok(data)
+    }
+}
+
+/// 相対パスは include_paths から探し、見つからなければそのまま返す
+fn resolve_stl_path(path: &str, include_paths: &[PathBuf]) -> PathBuf {
+    let raw = Path::new(path);
+    if raw.is_absolute() {
+        return raw.to_path_buf();
+    }
+    include_paths
+        .iter()
+        .map(|dir| dir.join(raw))
+        .find(|p| p.exists())
+        .unwrap_or_else(|| raw.to_path_buf())
+}
+
+fn read_stl_mesh(path: &Path) -> std::io::Result<StlMeshData> {
+    let mut file = std::fs::File::open(path)?;
+    let stl = stl_io::read_stl(&mut file)?;
+    let verts: Vec<f32> = stl
+        .vertices
+        .iter()
+        .flat_map(|v| [v[0], v[1], v[2]])
+        .collect();
+    let indices: Vec<u32> = stl
+        .faces
+        .iter()
+        .flat_map(|f| f.vertices.iter().map(|&i| i as u32))
+        .collect();
+    Ok(Arc::new((verts, indices)))
+}
+
+/// 座標を tolerance の格子に丸める
+fn snap_positions(positions: Vec<f32>, tolerance: f64) -> Vec<f32> {
+    let tol = tolerance as f32;
+    positions
+        .into_iter()
+        .map(|v| (v / tol).round() * tol)
+        .collect()
+}
+
+/// 頂点座標を tolerance の格子に丸めて、ほぼ一致する面を完全に一致させる。
+/// manifold-rs 0.6.4 は tolerance の設定を公開していないため、メッシュの座標を直接丸める
+fn snap_to_tolerance(m: Manifold, tolerance: Option<f64>) -> Manifold {
+    let Some(tol) = tolerance else {
+        return m;
+    };
+    let mesh = m.to_mesh();
+    let snapped = snap_positions(mesh_positions(&mesh), tol);
+    Manifold::from_mesh(Mesh::new(&snapped, &mesh.indices()))
+}
+
 #[derive(Debug, Clone)]
 pub enum Model2D {
     SketchXY(Plane2D),
@@ -106,7 +477,7 @@ pub enum Model2D {
 #[derive(Debug, Clone)]
 pub enum Plane2D {
     Sketch { points: Vec<(f64, f64)> },
-    Circle { radius: f64 },
+    Circle { radius: f64, segments: Option<u32> },
 }
 
 const DEFAULT_SEGMENTS: u32 = 32;
@@ -116,6 +487,7 @@ pub const BUILTIN_FUNCTORS: &[(&str, &[usize])] = &[
     ("sphere", &[1, 2]),
     ("cylinder", &[2, 3]),
     ("tetrahedron", &[0]),
+    ("empty", &[0]),
     ("union", &[2]),
     ("difference", &[2]),
     ("intersection", &[2]),
@@ -123,6 +495,7 @@ pub const BUILTIN_FUNCTORS: &[(&str, &[usize])] = &[
     ("translate", &[4]),
     ("scale", &[4]),
     ("rotate", &[4]),
+    ("warp", &[4]),
     ("p", &[2, 3]),
     ("sketchXY", &[1]),
     ("sketchYZ", &[1]),
@@ -137,6 +510,12 @@ pub const BUILTIN_FUNCTORS: &[(&str, &[usize])] = &[
     ("path", &[2]),
     ("sweep_extrude", &[2]),
     ("control", &[3, 4]),
+    ("let_shape", &[3]),
+    ("with_tolerance", &[2]),
+    ("with_segments", &[2]),
+    // decompose / convex_parts が作る内部用の項。`_` で始まる名前は変数になるので利用者の述語と衝突しない
+    ("__component", &[2]),
+    ("center", &[1]),
 ];
 
 inventory::submit! {
@@ -146,12 +525,85 @@ inventory::submit! {
     }
 }
 
+// warp の座標式の x, y, z はゴールではないので書き換えない
+inventory::submit! {
+    crate::term_processor::BuiltinRawArgs {
+        functor: "warp",
+        arity: 4,
+        indices: &[1, 2, 3],
+    }
+}
+
+/// 組み込み functor の引数の意味。同じ名前で受け付けるアリティごとに1つずつ並べる（エディタの補完用）
+pub struct FunctorSignature {
+    pub name: &'static str,
+    pub params: &'static [&'static str],
+}
+
+pub const FUNCTOR_SIGNATURES: &[FunctorSignature] = &[
+    sig("cube", &["x", "y", "z"]),
+    sig("sphere", &["radius"]),
+    sig("sphere", &["radius", "segments"]),
+    sig("cylinder", &["radius", "height"]),
+    sig("cylinder", &["radius", "height", "segments"]),
+    sig("tetrahedron", &[]),
+    sig("empty", &[]),
+    sig("union", &["a", "b"]),
+    sig("difference", &["a", "b"]),
+    sig("intersection", &["a", "b"]),
+    sig("hull", &["a", "b"]),
+    sig("translate", &["shape", "x", "y", "z"]),
+    sig("scale", &["shape", "x", "y", "z"]),
+    sig("rotate", &["shape", "x", "y", "z"]),
+    sig("warp", &["shape", "x_expr", "y_expr", "z_expr"]),
+    sig("p", &["x", "y"]),
+    sig("p", &["x", "y", "z"]),
+    sig("sketchXY", &["points"]),
+    sig("sketchYZ", &["points"]),
+    sig("sketchXZ", &["points"]),
+    sig("circle", &["radius"]),
+    sig("circle", &["radius", "segments"]),
+    sig("linear_extrude", &["profile", "height"]),
+    sig(
+        "complex_extrude",
+        &["profile", "height", "twist", "scale_x", "scale_y"],
+    ),
+    sig("revolve", &["profile", "degrees"]),
+    sig("revolve", &["profile", "degrees", "segments"]),
+    sig("stl", &["path"]),
+    sig("line_to", &["end"]),
+    sig("bezier_to", &["control", "end"]),
+    sig("bezier_to", &["control1", "control2", "end"]),
+    sig("path", &["start", "segments"]),
+    sig("sweep_extrude", &["profile", "path"]),
+    sig("control", &["x", "y", "z"]),
+    sig("control", &["x", "y", "z", "name"]),
+    sig("let_shape", &["name", "shape", "body"]),
+    sig("with_tolerance", &["shape", "tolerance"]),
+    sig("with_segments", &["shape", "segments"]),
+    sig("__component", &["shape", "index"]),
+    sig("center", &["shape"]),
+];
+
+const fn sig(name: &'static str, params: &'static [&'static str]) -> FunctorSignature {
+    FunctorSignature { name, params }
+}
+
+/// name/arity に対応する引数名
+pub fn functor_signature(name: &str, arity: usize) -> Option<&'static [&'static str]> {
+    FUNCTOR_SIGNATURES
+        .iter()
+        .find(|s| s.name == name && s.params.len() == arity)
+        .map(|s| s.params)
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum FunctorTag {
     Cube,
     Sphere,
     Cylinder,
     Tetrahedron,
+    Empty,
     Union,
     Difference,
     Intersection,
@@ -159,6 +611,7 @@ enum FunctorTag {
     Translate,
     Scale,
     Rotate,
+    Warp,
     Point,
     SketchXY,
     SketchYZ,
@@ -173,6 +626,11 @@ enum FunctorTag {
     Path,
     SweepExtrude,
     Control,
+    LetShape,
+    WithTolerance,
+    WithSegments,
+    Component,
+    Center,
 }
 
 impl FromStr for FunctorTag {
@@ -183,6 +641,7 @@ impl FromStr for FunctorTag {
             "sphere" => Ok(FunctorTag::Sphere),
             "cylinder" => Ok(FunctorTag::Cylinder),
             "tetrahedron" => Ok(FunctorTag::Tetrahedron),
+            "empty" => Ok(FunctorTag::Empty),
             "union" => Ok(FunctorTag::Union),
             "difference" => Ok(FunctorTag::Difference),
             "intersection" => Ok(FunctorTag::Intersection),
@@ -190,6 +649,7 @@ impl FromStr for FunctorTag {
             "translate" => Ok(FunctorTag::Translate),
             "scale" => Ok(FunctorTag::Scale),
             "rotate" => Ok(FunctorTag::Rotate),
+            "warp" => Ok(FunctorTag::Warp),
             "p" => Ok(FunctorTag::Point),
             "sketchXY" => Ok(FunctorTag::SketchXY),
             "sketchYZ" => Ok(FunctorTag::SketchYZ),
@@ -204,6 +664,11 @@ impl FromStr for FunctorTag {
             "path" => Ok(FunctorTag::Path),
             "sweep_extrude" => Ok(FunctorTag::SweepExtrude),
             "control" => Ok(FunctorTag::Control),
+            "let_shape" => Ok(FunctorTag::LetShape),
+            "with_tolerance" => Ok(FunctorTag::WithTolerance),
+            "with_segments" => Ok(FunctorTag::WithSegments),
+            "__component" => Ok(FunctorTag::Component),
+            "center" => Ok(FunctorTag::Center),
             _ => Err(()),
         }
     }
@@ -216,6 +681,7 @@ impl fmt::Display for FunctorTag {
             FunctorTag::Sphere => "sphere",
             FunctorTag::Cylinder => "cylinder",
             FunctorTag::Tetrahedron => "tetrahedron",
+            FunctorTag::Empty => "empty",
             FunctorTag::Union => "union",
             FunctorTag::Difference => "difference",
             FunctorTag::Intersection => "intersection",
@@ -223,6 +689,7 @@ impl fmt::Display for FunctorTag {
             FunctorTag::Translate => "translate",
             FunctorTag::Scale => "scale",
             FunctorTag::Rotate => "rotate",
+            FunctorTag::Warp => "warp",
             FunctorTag::Point => "p",
             FunctorTag::SketchXY => "sketchXY",
             FunctorTag::SketchYZ => "sketchYZ",
@@ -237,6 +704,11 @@ impl fmt::Display for FunctorTag {
             FunctorTag::Path => "path",
             FunctorTag::SweepExtrude => "sweep_extrude",
             FunctorTag::Control => "control",
+            FunctorTag::LetShape => "let_shape",
+            FunctorTag::WithTolerance => "with_tolerance",
+            FunctorTag::WithSegments => "with_segments",
+            FunctorTag::Component => "__component",
+            FunctorTag::Center => "center",
         };
         f.write_str(s)
     }
@@ -259,11 +731,39 @@ pub enum ConversionError {
         arg_index: usize,
         expected: &'static str,
     },
-    UnboundVariable(String),
+    UnboundVariable {
+        name: String,
+        span: Option<SrcSpan>,
+    },
+    /// 型は合っているが値が範囲外（負の分割数など）
+    InvalidValue {
+        functor: String,
+        arg_index: usize,
+        expected: &'static str,
+        got: String,
+        span: Option<SrcSpan>,
+    },
     IoError {
         functor: String,
         message: String,
     },
+    InvalidGeometry(String),
+    /// 0 除算など、有限の数値にならない引数
+    NonFiniteValue {
+        functor: String,
+        arg_index: usize,
+        span: Option<SrcSpan>,
+    },
+    /// 形状同士の `/`。left / right は該当する部分項の表示
+    ShapeDivision {
+        left: String,
+        right: String,
+    },
+    /// 複数のトップレベル形状を union するとき、index 番目の変換に失敗した
+    InElement {
+        index: usize,
+        source: Box<ConversionError>,
+    },
 }
 
 impl fmt::Display for ConversionError {
@@ -294,12 +794,49 @@ impl fmt::Display for ConversionError {
                     functor, arg_index, expected
                 )
             }
-            ConversionError::UnboundVariable(name) => {
+            ConversionError::UnboundVariable { name, .. } => {
                 write!(f, "Unbound variable: {}", name)
             }
+            ConversionError::InvalidValue {
+                functor,
+                arg_index,
+                expected,
+                got,
+                ..
+            } => {
+                write!(
+                    f,
+                    "Invalid value for {} arg {}: expected {}, got {}",
+                    functor, arg_index, expected, got
+                )
+            }
             ConversionError::IoError { functor, message } => {
                 write!(f, "I/O error in {}: {}", functor, message)
             }
+            ConversionError::InvalidGeometry(reason) => {
+                write!(f, "Invalid geometry: {}", reason)
+            }
+            ConversionError::NonFiniteValue {
+                functor, arg_index, ..
+            } => {
+                write!(
+                    f,
+                    "Non-finite value for {} arg {} (division by zero?)",
+                    functor, arg_index
+                )
+            }
+            ConversionError::ShapeDivision { left, right } => {
+                write!(
+                    f,
+                    "Cannot divide shapes: {} / {}. Use difference (a - b) to cut a shape \
+                     or intersection (a * b) to keep the overlap; `/` only works between numbers \
+                     (e.g. cube(10 / 2, 1, 1))",
+                    left, right
+                )
+            }
+            ConversionError::InElement { index, source } => {
+                write!(f, "element {} of union: {}", index, source)
+            }
         }
     }
 }
@@ -311,7 +848,13 @@ impl crate::term_rewrite::CadhrError for ConversionError {
         self.to_string()
     }
     fn span(&self) -> Option<SrcSpan> {
-        None
+        match self {
+            ConversionError::UnboundVariable { span, .. }
+            | ConversionError::InvalidValue { span, .. }
+            | ConversionError::NonFiniteValue { span, .. } => *span,
+            ConversionError::InElement { source, .. } => source.span(),
+            _ => None,
+        }
     }
 }
 
@@ -534,14 +1077,41 @@ fn apply_var_overrides_to_term<S>(
 // Args: 引数抽出用ヘルパー
 // ============================================================
 
+/// 除数が 0 に評価される `/` を含むか
+fn has_zero_divisor<S>(term: &Term<S>) -> bool {
+    match term {
+        Term::InfixExpr { op, left, right } => {
+            (*op == ArithOp::Div
+                && crate::term_rewrite::eval_arith(right, true).value()
+                    == Some(FixedPoint::from_int(0)))
+                || has_zero_divisor(left)
+                || has_zero_divisor(right)
+        }
+        _ => false,
+    }
+}
+
 struct Args<'a, S> {
     args: &'a [Term<S>],
     functor: &'a str,
+    shapes: &'a ShapeBindings,
 }
 
 impl<'a, S> Args<'a, S> {
     fn new(functor: &'a str, args: &'a [Term<S>]) -> Self {
-        Self { args, functor }
+        Self {
+            args,
+            functor,
+            shapes: &[],
+        }
+    }
+
+    fn with_shapes(functor: &'a str, args: &'a [Term<S>], shapes: &'a ShapeBindings) -> Self {
+        Self {
+            args,
+            functor,
+            shapes,
+        }
     }
 
     fn len(&self) -> usize {
@@ -549,7 +1119,7 @@ impl<'a, S> Args<'a, S> {
     }
 
     fn f64(&self, i: usize) -> Result<f64, ConversionError> {
-        if let Some(fp) = crate::term_rewrite::try_eval_to_number(&self.args[i]) {
+        if let Some(fp) = crate::term_rewrite::eval_arith(&self.args[i], true).value() {
             return Ok(fp.to_f64());
         }
         if let Some((fp, _)) = term_as_fixed_point(&self.args[i]) {
@@ -564,7 +1134,17 @@ impl<'a, S> Args<'a, S> {
                 let mid = (lo.value.to_f64() + hi.value.to_f64()) / 2.0;
                 Ok(mid)
             }
-            Term::Var { name, .. } => Err(ConversionError::UnboundVariable(name.clone())),
+            Term::Var { name, span, .. } => Err(ConversionError::UnboundVariable {
+                name: name.clone(),
+                span: *span,
+            }),
+            term @ Term::InfixExpr { .. } if has_zero_divisor(term) => {
+                Err(ConversionError::NonFiniteValue {
+                    functor: self.functor.to_string(),
+                    arg_index: i,
+                    span: first_span(term),
+                })
+            }
             Term::Number { .. }
             | Term::InfixExpr { .. }
             | Term::Struct { .. }
@@ -578,6 +1158,42 @@ impl<'a, S> Args<'a, S> {
         }
     }
 
+    fn u32(&self, i: usize) -> Result<u32, ConversionError> {
+        let value = self.f64(i)?;
+        if value < 0.0 || value.fract() != 0.0 || value > u32::MAX as f64 {
+            return Err(self.invalid_value(i, "non-negative integer", value));
+        }
+        Ok(value as u32)
+    }
+
+    /// 円や回転体の分割数。3 未満では面を張れない
+    fn segments(&self, i: usize) -> Result<u32, ConversionError> {
+        let segments = self.u32(i)?;
+        if segments < 3 {
+            return Err(self.invalid_value(i, "integer >= 3", segments as f64));
+        }
+        Ok(segments)
+    }
+
+    /// 0 倍は形状を平面に潰して多様体でなくなるので拒否する
+    fn scale_factor(&self, i: usize) -> Result<f64, ConversionError> {
+        let value = self.f64(i)?;
+        if value.abs() < MIN_SCALE_FACTOR {
+            return Err(self.invalid_value(i, "non-zero scale factor", value));
+        }
+        Ok(value)
+    }
+
+    fn invalid_value(&self, i: usize, expected: &'static str, got: f64) -> ConversionError {
+        ConversionError::InvalidValue {
+            functor: self.functor.to_string(),
+            arg_index: i,
+            expected,
+            got: got.to_string(),
+            span: first_span(&self.args[i]),
+        }
+    }
+
     fn string(&self, i: usize) -> Result<String, ConversionError> {
         match &self.args[i] {
             Term::StringLit { value } => Ok(value.clone()),
@@ -589,12 +1205,49 @@ impl<'a, S> Args<'a, S> {
         }
     }
 
+    fn warp_expr(&self, i: usize) -> Result<WarpExpr, ConversionError> {
+        fn convert<S>(term: &Term<S>) -> Option<WarpExpr> {
+            if let Some((fp, _)) = term_as_fixed_point(term) {
+                return Some(WarpExpr::Num(fp.to_f64()));
+            }
+            match term {
+                Term::Struct { functor, args, .. } if args.is_empty() => {
+                    let axis = ["x", "y", "z"].iter().position(|c| c == functor)?;
+                    Some(WarpExpr::Coord(axis))
+                }
+                Term::InfixExpr { op, left, right } => Some(WarpExpr::BinOp(
+                    *op,
+                    Box::new(convert(left)?),
+                    Box::new(convert(right)?),
+                )),
+                _ => None,
+            }
+        }
+        convert(&self.args[i]).ok_or_else(|| ConversionError::TypeMismatch {
+            functor: self.functor.to_string(),
+            arg_index: i,
+            expected: "arithmetic over numbers and x, y, z",
+        })
+    }
+
     fn term_3d(&self, i: usize) -> Result<Model3D, ConversionError> {
-        Model3D::from_term(&self.args[i])
+        Model3D::from_term_with(&self.args[i], self.shapes)
     }
 
+    /// 3D 形状が渡された場合は、どの引数かが分かる TypeMismatch にする
     fn term_2d(&self, i: usize) -> Result<Model2D, ConversionError> {
-        Model2D::from_term(&self.args[i])
+        Model2D::from_term(&self.args[i]).map_err(|e| match (&e, &self.args[i]) {
+            (ConversionError::UnknownPrimitive(_), Term::Struct { functor, .. })
+                if FunctorTag::from_str(functor).is_ok() =>
+            {
+                ConversionError::TypeMismatch {
+                    functor: self.functor.to_string(),
+                    arg_index: i,
+                    expected: "2D profile",
+                }
+            }
+            _ => e,
+        })
     }
 
     fn arity_error(&self, expected: &str) -> ConversionError {
@@ -777,15 +1430,26 @@ fn extract_path_points<S>(
     Ok(points)
 }
 
+/// 形状同士の `/` は意味を持たないので、両辺を示して代わりの演算を案内する
+fn shape_division_error<S>(left: &Term<S>, right: &Term<S>) -> ConversionError {
+    ConversionError::ShapeDivision {
+        left: left.elided(ERROR_TERM_DEPTH).to_string(),
+        right: right.elided(ERROR_TERM_DEPTH).to_string(),
+    }
+}
+
 impl Model2D {
     fn from_term<S>(term: &Term<S>) -> Result<Self, ConversionError> {
         match term {
             Term::Struct { functor, args, .. } => Self::from_struct(functor, args),
             Term::InfixExpr { op, left, right } => Self::from_infix_expr(*op, left, right),
-            Term::Var { name, .. } => Err(ConversionError::UnboundVariable(name.clone())),
+            Term::Var { name, span, .. } => Err(ConversionError::UnboundVariable {
+                name: name.clone(),
+                span: *span,
+            }),
             _ => Err(ConversionError::UnknownPrimitive(format!(
-                "expected 2D profile, got {:?}",
-                term
+                "expected 2D profile, got {}",
+                term.elided(ERROR_TERM_DEPTH)
             ))),
         }
     }
@@ -801,9 +1465,7 @@ impl Model2D {
             ArithOp::Add => Ok(Model2D::Union(left_expr, right_expr)),
             ArithOp::Sub => Ok(Model2D::Difference(left_expr, right_expr)),
             ArithOp::Mul => Ok(Model2D::Intersection(left_expr, right_expr)),
-            ArithOp::Div => Err(ConversionError::UnknownPrimitive(
-                "division operator (/) is not supported for CAD operations".to_string(),
-            )),
+            ArithOp::Div => Err(shape_division_error(left, right)),
         }
     }
 
@@ -835,13 +1497,14 @@ impl Model2D {
             }
             FunctorTag::SketchXZ => Err(a.arity_error("1")),
 
-            FunctorTag::Circle if a.len() == 1 => {
-                Ok(Model2D::SketchXY(Plane2D::Circle { radius: a.f64(0)? }))
-            }
-            FunctorTag::Circle if a.len() == 2 => {
-                // segments引数は無視（常にDEFAULT_SEGMENTS）
-                Ok(Model2D::SketchXY(Plane2D::Circle { radius: a.f64(0)? }))
-            }
+            FunctorTag::Circle if a.len() == 1 => Ok(Model2D::SketchXY(Plane2D::Circle {
+                radius: a.f64(0)?,
+                segments: None,
+            })),
+            FunctorTag::Circle if a.len() == 2 => Ok(Model2D::SketchXY(Plane2D::Circle {
+                radius: a.f64(0)?,
+                segments: Some(a.segments(1)?),
+            })),
             FunctorTag::Circle => Err(a.arity_error("1 or 2")),
 
             FunctorTag::Path if a.len() == 2 => {
@@ -870,7 +1533,8 @@ impl Model2D {
         }
     }
 
-    fn to_polygon_rings(&self) -> Option<Vec<Vec<f64>>> {
+    /// 円を segments 角形で近似して多角形リングにする
+    fn to_polygon_rings_with(&self, segments: u32) -> Option<Vec<Vec<f64>>> {
         match self {
             Model2D::SketchXY(Plane2D::Sketch { points })
             | Model2D::SketchYZ(Plane2D::Sketch { points })
@@ -880,20 +1544,34 @@ impl Model2D {
                 ensure_ccw(&mut pts);
                 Some(vec![pairs_to_flat(&pts)])
             }
-            Model2D::SketchXY(Plane2D::Circle { radius }) | Model2D::SketchYZ(Plane2D::Circle { radius })
-            | Model2D::SketchXZ(Plane2D::Circle { radius }) => {
-                let points: Vec<f64> = (0..DEFAULT_SEGMENTS)
+            Model2D::SketchXY(Plane2D::Circle {
+                radius,
+                segments: own,
+            })
+            | Model2D::SketchYZ(Plane2D::Circle {
+                radius,
+                segments: own,
+            })
+            | Model2D::SketchXZ(Plane2D::Circle {
+                radius,
+                segments: own,
+            }) => {
+                let segments = own.unwrap_or(segments);
+                let points: Vec<f64> = (0..segments)
                     .flat_map(|i| {
-                        let angle =
-                            2.0 * std::f64::consts::PI * (i as f64) / (DEFAULT_SEGMENTS as f64);
+                        let angle = 2.0 * std::f64::consts::PI * (i as f64) / (segments as f64);
                         [radius * angle.cos(), radius * angle.sin()]
                     })
                     .collect();
                 Some(vec![points])
             }
-            Model2D::Union(a, b) => polygon_boolean_2d(a, b, |ma, mb| ma.union(mb)),
-            Model2D::Difference(a, b) => polygon_boolean_2d(a, b, |ma, mb| ma.difference(mb)),
-            Model2D::Intersection(a, b) => polygon_boolean_2d(a, b, |ma, mb| ma.intersection(mb)),
+            Model2D::Union(a, b) => polygon_boolean_2d(a, b, segments, |ma, mb| ma.union(mb)),
+            Model2D::Difference(a, b) => {
+                polygon_boolean_2d(a, b, segments, |ma, mb| ma.difference(mb))
+            }
+            Model2D::Intersection(a, b) => {
+                polygon_boolean_2d(a, b, segments, |ma, mb| ma.intersection(mb))
+            }
         }
     }
 
@@ -912,10 +1590,11 @@ const THIN_EXTRUDE_HEIGHT: f64 = 1.0;
 fn polygon_boolean_2d(
     a: &Model2D,
     b: &Model2D,
+    segments: u32,
     op: impl FnOnce(&Manifold, &Manifold) -> Manifold,
 ) -> Option<Vec<Vec<f64>>> {
-    let rings_a = a.to_polygon_rings()?;
-    let rings_b = b.to_polygon_rings()?;
+    let rings_a = a.to_polygon_rings_with(segments)?;
+    let rings_b = b.to_polygon_rings_with(segments)?;
     let refs_a: Vec<&[f64]> = rings_a.iter().map(|r| r.as_slice()).collect();
     let refs_b: Vec<&[f64]> = rings_b.iter().map(|r| r.as_slice()).collect();
     let ma = Manifold::extrude(&refs_a, THIN_EXTRUDE_HEIGHT, 0, 0.0, 1.0, 1.0);
@@ -932,9 +1611,10 @@ fn polygon_boolean_2d(
 fn polygon_rings_or_err(
     profile: &Model2D,
     functor: &str,
+    segments: u32,
 ) -> Result<Vec<Vec<f64>>, ConversionError> {
     profile
-        .to_polygon_rings()
+        .to_polygon_rings_with(segments)
         .ok_or_else(|| ConversionError::TypeMismatch {
             functor: functor.to_string(),
             arg_index: 0,
@@ -959,14 +1639,28 @@ fn apply_plane_rotation(m: Manifold, profile: &Model2D) -> Manifold {
 
 impl Model3D {
     pub fn from_term<S>(term: &Term<S>) -> Result<Self, ConversionError> {
+        Self::from_term_with(term, &[])
+    }
+
+    fn from_term_with<S>(term: &Term<S>, shapes: &ShapeBindings) -> Result<Self, ConversionError> {
         match term {
-            Term::Struct { functor, args, .. } => Self::from_struct(functor, args),
-            Term::InfixExpr { op, left, right } => Self::from_infix_expr(*op, left, right),
-            Term::Var { name, .. } => Err(ConversionError::UnboundVariable(name.clone())),
+            Term::Struct { functor, args, .. } => Self::from_struct(functor, args, shapes),
+            Term::InfixExpr { op, left, right } => Self::from_infix_expr(*op, left, right, shapes),
+            Term::Var { name, span, .. } => shapes
+                .iter()
+                .rev()
+                .find(|(n, _)| n == name)
+                .map(|(_, shared)| Model3D::Shared(shared.clone()))
+                .ok_or_else(|| ConversionError::UnboundVariable {
+                    name: name.clone(),
+                    span: *span,
+                }),
             Term::Constraint { .. } => Err(ConversionError::UnknownPrimitive(
                 "constraint should not reach mesh generation".to_string(),
             )),
-            _ => Err(ConversionError::UnknownPrimitive(format!("{:?}", term))),
+            _ => Err(ConversionError::UnknownPrimitive(
+                term.elided(ERROR_TERM_DEPTH).to_string(),
+            )),
         }
     }
 
@@ -976,24 +1670,27 @@ impl Model3D {
         op: ArithOp,
         left: &Term<S>,
         right: &Term<S>,
+        shapes: &ShapeBindings,
     ) -> Result<Self, ConversionError> {
         // depth-first: まず2Dとして両辺を試み、両方成功したら2Dを含む3D(extrude)ではなく
         // 呼び出し元が3Dを期待しているので、3Dとして解釈する
-        let left_expr = Box::new(Self::from_term(left)?);
-        let right_expr = Box::new(Self::from_term(right)?);
+        let left_expr = Box::new(Self::from_term_with(left, shapes)?);
+        let right_expr = Box::new(Self::from_term_with(right, shapes)?);
 
         match op {
             ArithOp::Add => Ok(Model3D::Union(left_expr, right_expr)),
             ArithOp::Sub => Ok(Model3D::Difference(left_expr, right_expr)),
             ArithOp::Mul => Ok(Model3D::Intersection(left_expr, right_expr)),
-            ArithOp::Div => Err(ConversionError::UnknownPrimitive(
-                "division operator (/) is not supported for CAD operations".to_string(),
-            )),
+            ArithOp::Div => Err(shape_division_error(left, right)),
         }
     }
 
-    fn from_struct<S>(functor: &str, args: &[Term<S>]) -> Result<Self, ConversionError> {
-        let a = Args::new(functor, args);
+    fn from_struct<S>(
+        functor: &str,
+        args: &[Term<S>],
+        shapes: &ShapeBindings,
+    ) -> Result<Self, ConversionError> {
+        let a = Args::with_shapes(functor, args, shapes);
         let tag = FunctorTag::from_str(functor)
             .map_err(|_| ConversionError::UnknownPrimitive(functor.to_string()))?;
 
@@ -1005,29 +1702,34 @@ impl Model3D {
             }),
             FunctorTag::Cube => Err(a.arity_error("3")),
 
-            FunctorTag::Sphere if a.len() == 1 => Ok(Model3D::Sphere { radius: a.f64(0)? }),
-            FunctorTag::Sphere if a.len() == 2 => {
-                // segments引数は無視（常にDEFAULT_SEGMENTS）
-                Ok(Model3D::Sphere { radius: a.f64(0)? })
-            }
+            FunctorTag::Sphere if a.len() == 1 => Ok(Model3D::Sphere {
+                radius: a.f64(0)?,
+                segments: None,
+            }),
+            FunctorTag::Sphere if a.len() == 2 => Ok(Model3D::Sphere {
+                radius: a.f64(0)?,
+                segments: Some(a.segments(1)?),
+            }),
             FunctorTag::Sphere => Err(a.arity_error("1 or 2")),
 
             FunctorTag::Cylinder if a.len() == 2 => Ok(Model3D::Cylinder {
                 radius: a.f64(0)?,
                 height: a.f64(1)?,
+                segments: None,
+            }),
+            FunctorTag::Cylinder if a.len() == 3 => Ok(Model3D::Cylinder {
+                radius: a.f64(0)?,
+                height: a.f64(1)?,
+                segments: Some(a.segments(2)?),
             }),
-            FunctorTag::Cylinder if a.len() == 3 => {
-                // segments引数は無視（常にDEFAULT_SEGMENTS）
-                Ok(Model3D::Cylinder {
-                    radius: a.f64(0)?,
-                    height: a.f64(1)?,
-                })
-            }
             FunctorTag::Cylinder => Err(a.arity_error("2 or 3")),
 
             FunctorTag::Tetrahedron if a.len() == 0 => Ok(Model3D::Tetrahedron),
             FunctorTag::Tetrahedron => Err(a.arity_error("0")),
 
+            FunctorTag::Empty if a.len() == 0 => Ok(Model3D::Empty),
+            FunctorTag::Empty => Err(a.arity_error("0")),
+
             FunctorTag::Union if a.len() == 2 => Ok(Model3D::Union(
                 Box::new(a.term_3d(0)?),
                 Box::new(a.term_3d(1)?),
@@ -1062,9 +1764,9 @@ impl Model3D {
 
             FunctorTag::Scale if a.len() == 4 => Ok(Model3D::Scale {
                 model: Box::new(a.term_3d(0)?),
-                x: a.f64(1)?,
-                y: a.f64(2)?,
-                z: a.f64(3)?,
+                x: a.scale_factor(1)?,
+                y: a.scale_factor(2)?,
+                z: a.scale_factor(3)?,
             }),
             FunctorTag::Scale => Err(a.arity_error("4")),
 
@@ -1076,6 +1778,12 @@ impl Model3D {
             }),
             FunctorTag::Rotate => Err(a.arity_error("4")),
 
+            FunctorTag::Warp if a.len() == 4 => Ok(Model3D::Warp {
+                model: Box::new(a.term_3d(0)?),
+                exprs: [a.warp_expr(1)?, a.warp_expr(2)?, a.warp_expr(3)?],
+            }),
+            FunctorTag::Warp => Err(a.arity_error("4")),
+
             FunctorTag::LinearExtrude if a.len() == 2 => Ok(Model3D::LinearExtrude {
                 profile: a.term_2d(0)?,
                 height: a.f64(1)?,
@@ -1094,14 +1802,13 @@ impl Model3D {
             FunctorTag::Revolve if a.len() == 2 => Ok(Model3D::Revolve {
                 profile: a.term_2d(0)?,
                 degrees: a.f64(1)?,
+                segments: None,
+            }),
+            FunctorTag::Revolve if a.len() == 3 => Ok(Model3D::Revolve {
+                profile: a.term_2d(0)?,
+                degrees: a.f64(1)?,
+                segments: Some(a.segments(2)?),
             }),
-            FunctorTag::Revolve if a.len() == 3 => {
-                // segments引数は無視（常にDEFAULT_SEGMENTS）
-                Ok(Model3D::Revolve {
-                    profile: a.term_2d(0)?,
-                    degrees: a.f64(1)?,
-                })
-            }
             FunctorTag::Revolve => Err(a.arity_error("2 or 3")),
 
             FunctorTag::Stl if a.len() == 1 => {
@@ -1110,26 +1817,65 @@ impl Model3D {
             }
             FunctorTag::Stl => Err(a.arity_error("1")),
 
-            FunctorTag::SweepExtrude if a.len() == 2 => {
-                let profile_2d = a.term_2d(0)?;
-                let path_2d = a.term_2d(1)?;
-                let profile_rings = polygon_rings_or_err(&profile_2d, "sweep_extrude")?;
-                let path_rings =
-                    path_2d
-                        .to_polygon_rings()
-                        .ok_or_else(|| ConversionError::TypeMismatch {
-                            functor: "sweep_extrude".to_string(),
-                            arg_index: 1,
-                            expected: "path data",
-                        })?;
-                let profile_data = flat_to_pairs(&profile_rings[0]);
-                let path_data = flat_to_pairs(&path_rings[0]);
-                Ok(Model3D::SweepExtrude {
-                    profile_data,
-                    path_data,
+            FunctorTag::SweepExtrude if a.len() == 2 => Ok(Model3D::SweepExtrude {
+                profile: a.term_2d(0)?,
+                path: a.term_2d(1)?,
+            }),
+            FunctorTag::SweepExtrude => Err(a.arity_error("2")),
+
+            FunctorTag::LetShape if a.len() == 3 => {
+                let name = match &a.args[0] {
+                    Term::Var { name, .. } => name.clone(),
+                    _ => {
+                        return Err(ConversionError::TypeMismatch {
+                            functor: a.functor.to_string(),
+                            arg_index: 0,
+                            expected: "variable",
+                        });
+                    }
+                };
+                let shared = Arc::new(SharedShape::new(name.clone(), a.term_3d(1)?));
+                let mut scoped = shapes.to_vec();
+                scoped.push((name, shared));
+                Model3D::from_term_with(&a.args[2], &scoped)
+            }
+            FunctorTag::LetShape => Err(a.arity_error("3")),
+
+            FunctorTag::WithTolerance if a.len() == 2 => {
+                let tolerance = a.f64(1)?;
+                if tolerance <= 0.0 {
+                    return Err(ConversionError::TypeMismatch {
+                        functor: a.functor.to_string(),
+                        arg_index: 1,
+                        expected: "positive number",
+                    });
+                }
+                Ok(Model3D::WithTolerance {
+                    model: Box::new(a.term_3d(0)?),
+                    tolerance,
                 })
             }
-            FunctorTag::SweepExtrude => Err(a.arity_error("2")),
+            FunctorTag::WithTolerance => Err(a.arity_error("2")),
+
+            FunctorTag::WithSegments if a.len() == 2 => Ok(Model3D::WithSegments {
+                model: Box::new(a.term_3d(0)?),
+                segments: a.segments(1)?,
+            }),
+            FunctorTag::WithSegments => Err(a.arity_error("2")),
+
+            FunctorTag::Component if a.len() == 2 => {
+                let index = a.u32(1)?;
+                Ok(Model3D::Component {
+                    model: Box::new(a.term_3d(0)?),
+                    index: index as usize,
+                })
+            }
+            FunctorTag::Component => Err(a.arity_error("2")),
+
+            FunctorTag::Center if a.len() == 1 => Ok(Model3D::Center {
+                model: Box::new(a.term_3d(0)?),
+            }),
+            FunctorTag::Center => Err(a.arity_error("1")),
 
             FunctorTag::Point => Err(ConversionError::UnknownPrimitive(
                 "p is a data constructor, not a shape primitive".to_string(),
@@ -1162,43 +1908,170 @@ impl Model3D {
 
     /// Model3D を manifold-rs の Manifold に評価
     pub fn evaluate(&self, include_paths: &[PathBuf]) -> Result<Manifold, ConversionError> {
+        self.evaluate_with_cache(include_paths, &EvalCache::default())
+    }
+
+    /// evaluate と同じだが、cache を他の式の評価と共有する
+    pub fn evaluate_with_cache(
+        &self,
+        include_paths: &[PathBuf],
+        cache: &EvalCache,
+    ) -> Result<Manifold, ConversionError> {
+        self.evaluate_in(&EvalContext {
+            include_paths,
+            tolerance: None,
+            segments: DEFAULT_SEGMENTS,
+            profile: None,
+            cache,
+        })
+    }
+
+    /// evaluate と同じだが、各ノードの評価時間を profile に記録する
+    pub fn evaluate_profiled(
+        &self,
+        include_paths: &[PathBuf],
+        profile: &EvalProfile,
+    ) -> Result<Manifold, ConversionError> {
+        self.evaluate_in(&EvalContext {
+            include_paths,
+            tolerance: None,
+            segments: DEFAULT_SEGMENTS,
+            profile: Some(profile),
+            cache: &EvalCache::default(),
+        })
+    }
+
+    fn evaluate_in(&self, ctx: &EvalContext) -> Result<Manifold, ConversionError> {
+        let Some(profile) = ctx.profile else {
+            return self.evaluate_node(ctx);
+        };
+        let start = Instant::now();
+        let result = self.evaluate_node(ctx);
+        profile.record(self.label(), start.elapsed());
+        result
+    }
+
+    /// tolerance 指定時はブーリアン演算の前後で頂点を格子に丸める
+    fn evaluate_node(&self, ctx: &EvalContext) -> Result<Manifold, ConversionError> {
+        let include_paths = ctx.include_paths;
+        let tolerance = ctx.tolerance;
+        let eval = |m: &Model3D| -> Result<Manifold, ConversionError> {
+            Ok(snap_to_tolerance(m.evaluate_in(ctx)?, tolerance))
+        };
         match self {
             Model3D::Cube { x, y, z } => Ok(Manifold::cube(*x, *y, *z)),
-            Model3D::Sphere { radius } => Ok(Manifold::sphere(*radius, DEFAULT_SEGMENTS)),
-            Model3D::Cylinder { radius, height } => Ok(Manifold::cylinder(
+            Model3D::Sphere { radius, segments } => {
+                Ok(Manifold::sphere(*radius, segments.unwrap_or(ctx.segments)))
+            }
+            Model3D::Cylinder {
+                radius,
+                height,
+                segments,
+            } => Ok(Manifold::cylinder(
                 *radius,
                 *radius,
                 *height,
-                DEFAULT_SEGMENTS,
+                segments.unwrap_or(ctx.segments),
             )),
             Model3D::Tetrahedron => Ok(Manifold::tetrahedron()),
+            Model3D::Empty => Ok(Manifold::empty()),
+            Model3D::Shared(shared) => shared.evaluate(ctx),
+            Model3D::WithTolerance { model, tolerance } => Ok(snap_to_tolerance(
+                model.evaluate_in(&EvalContext {
+                    tolerance: Some(*tolerance),
+                    ..*ctx
+                })?,
+                Some(*tolerance),
+            )),
+            Model3D::WithSegments { model, segments } => model.evaluate_in(&EvalContext {
+                segments: *segments,
+                ..*ctx
+            }),
+            Model3D::Component { model, index } => Ok(ctx
+                .cache
+                .components(
+                    model,
+                    &EvalContext {
+                        tolerance: None,
+                        segments: DEFAULT_SEGMENTS,
+                        ..*ctx
+                    },
+                )?
+                .get(*index)
+                .map(|(verts, indices)| Manifold::from_mesh(Mesh::new(verts, indices)))
+                .unwrap_or_else(Manifold::empty)),
+
+            Model3D::Union(..) => {
+                let operands = flatten_chain(self, Model3D::as_union)
+                    .into_iter()
+                    .map(eval)
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(reduce_balanced(operands, |a, b| {
+                    snap_to_tolerance(a.union(b), tolerance)
+                }))
+            }
+            Model3D::Intersection(..) => {
+                let operands = flatten_chain(self, Model3D::as_intersection)
+                    .into_iter()
+                    .map(eval)
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(reduce_balanced(operands, |a, b| {
+                    snap_to_tolerance(a.intersection(b), tolerance)
+                }))
+            }
+            Model3D::Difference(..) => {
+                // (a - b) - c = a - (b + c): 引く側をまとめて一度だけ difference する
+                let mut base = self;
+                let mut subtrahends = Vec::new();
+                while let Model3D::Difference(a, b) = base {
+                    subtrahends.push(eval(b)?);
+                    base = a;
+                }
+                let cutter =
+                    reduce_balanced(subtrahends, |a, b| snap_to_tolerance(a.union(b), tolerance));
+                Ok(eval(base)?.difference(&cutter))
+            }
+            Model3D::Hull(a, b) => Ok(eval(a)?.union(&eval(b)?).hull()),
 
-            Model3D::Union(a, b) => Ok(a
-                .evaluate(include_paths)?
-                .union(&b.evaluate(include_paths)?)),
-            Model3D::Difference(a, b) => Ok(a
-                .evaluate(include_paths)?
-                .difference(&b.evaluate(include_paths)?)),
-            Model3D::Intersection(a, b) => Ok(a
-                .evaluate(include_paths)?
-                .intersection(&b.evaluate(include_paths)?)),
-            Model3D::Hull(a, b) => Ok(a
-                .evaluate(include_paths)?
-                .union(&b.evaluate(include_paths)?)
-                .hull()),
-
-            Model3D::Translate { model, x, y, z } => {
-                Ok(model.evaluate(include_paths)?.translate(*x, *y, *z))
+            Model3D::Translate { .. } => {
+                let (inner, [x, y, z]) = self.flatten_translate();
+                Ok(inner.evaluate_in(ctx)?.translate(x, y, z))
             }
-            Model3D::Scale { model, x, y, z } => {
-                Ok(model.evaluate(include_paths)?.scale(*x, *y, *z))
+            Model3D::Scale { .. } => {
+                let (inner, [x, y, z]) = self.flatten_scale();
+                Ok(inner.evaluate_in(ctx)?.scale(x, y, z))
             }
-            Model3D::Rotate { model, x, y, z } => {
-                Ok(model.evaluate(include_paths)?.rotate(*x, *y, *z))
+            Model3D::Rotate { model, x, y, z } => Ok(model.evaluate_in(ctx)?.rotate(*x, *y, *z)),
+            Model3D::Center { model } => {
+                let m = model.evaluate_in(ctx)?;
+                let Some((min, max)) = mesh_bounds(&m.to_mesh()) else {
+                    return Ok(m);
+                };
+                let c: [f64; 3] = std::array::from_fn(|i| (min[i] + max[i]) / 2.0);
+                Ok(m.translate(-c[0], -c[1], -c[2]))
+            }
+            Model3D::Warp { model, exprs } => {
+                let mesh = model.evaluate_in(ctx)?.to_mesh();
+                let mut warped = Vec::new();
+                for p in mesh_positions(&mesh).chunks_exact(3) {
+                    let p = [p[0] as f64, p[1] as f64, p[2] as f64];
+                    for (axis, expr) in exprs.iter().enumerate() {
+                        let v = expr.eval(p);
+                        if !v.is_finite() {
+                            return Err(ConversionError::NonFiniteValue {
+                                functor: "warp".to_string(),
+                                arg_index: axis + 1,
+                                span: None,
+                            });
+                        }
+                        warped.push(v as f32);
+                    }
+                }
+                Ok(Manifold::from_mesh(Mesh::new(&warped, &mesh.indices())))
             }
 
             Model3D::LinearExtrude { profile, height } => {
-                let rings = polygon_rings_or_err(profile, "linear_extrude")?;
+                let rings = polygon_rings_or_err(profile, "linear_extrude", ctx.segments)?;
                 let refs: Vec<&[f64]> = rings.iter().map(|r| r.as_slice()).collect();
                 let m = Manifold::extrude(&refs, *height, 0, 0.0, 1.0, 1.0);
                 Ok(apply_plane_rotation(m, profile))
@@ -1210,62 +2083,44 @@ impl Model3D {
                 scale_x,
                 scale_y,
             } => {
-                let rings = polygon_rings_or_err(profile, "complex_extrude")?;
+                let rings = polygon_rings_or_err(profile, "complex_extrude", ctx.segments)?;
                 let refs: Vec<&[f64]> = rings.iter().map(|r| r.as_slice()).collect();
                 let n_divisions = (height.abs() as u32).max(1);
                 let m = Manifold::extrude(&refs, *height, n_divisions, *twist, *scale_x, *scale_y);
                 Ok(apply_plane_rotation(m, profile))
             }
-            Model3D::Revolve { profile, degrees } => {
-                let rings = polygon_rings_or_err(profile, "revolve")?;
+            Model3D::Revolve {
+                profile,
+                degrees,
+                segments,
+            } => {
+                let rings = polygon_rings_or_err(profile, "revolve", ctx.segments)?;
                 let refs: Vec<&[f64]> = rings.iter().map(|r| r.as_slice()).collect();
-                let m = Manifold::revolve(&refs, DEFAULT_SEGMENTS, *degrees);
+                let m = Manifold::revolve(&refs, segments.unwrap_or(ctx.segments), *degrees);
                 Ok(apply_plane_rotation(m, profile))
             }
 
-            Model3D::SweepExtrude {
-                profile_data,
-                path_data,
-            } => {
-                let (verts, indices) = crate::sweep::sweep_extrude_mesh(profile_data, path_data)?;
+            Model3D::SweepExtrude { profile, path } => {
+                let profile_rings = polygon_rings_or_err(profile, "sweep_extrude", ctx.segments)?;
+                let path_rings = path.to_polygon_rings_with(ctx.segments).ok_or_else(|| {
+                    ConversionError::TypeMismatch {
+                        functor: "sweep_extrude".to_string(),
+                        arg_index: 1,
+                        expected: "path data",
+                    }
+                })?;
+                let (verts, indices) = crate::sweep::sweep_extrude_mesh(
+                    &flat_to_pairs(&profile_rings[0]),
+                    &flat_to_pairs(&path_rings[0]),
+                )?;
                 let mesh = Mesh::new(&verts, &indices);
                 Ok(Manifold::from_mesh(mesh))
             }
 
             Model3D::Stl { path } => {
-                let raw = Path::new(path);
-                let resolved = if raw.is_absolute() {
-                    PathBuf::from(path)
-                } else {
-                    include_paths
-                        .iter()
-                        .map(|dir| dir.join(raw))
-                        .find(|p| p.exists())
-                        .unwrap_or_else(|| PathBuf::from(path))
-                };
-                let mut file = std::fs::OpenOptions::new()
-                    .read(true)
-                    .open(&resolved)
-                    .map_err(|e| ConversionError::IoError {
-                        functor: "stl".into(),
-                        message: format!("{}: {}", resolved.display(), e),
-                    })?;
-                let stl = stl_io::read_stl(&mut file).map_err(|e| ConversionError::IoError {
-                    functor: "stl".into(),
-                    message: format!("{}: {}", resolved.display(), e),
-                })?;
-                let verts: Vec<f32> = stl
-                    .vertices
-                    .iter()
-                    .flat_map(|v| [v[0], v[1], v[2]])
-                    .collect();
-                let indices: Vec<u32> = stl
-                    .faces
-                    .iter()
-                    .flat_map(|f| f.vertices.iter().map(|&i| i as u32))
-                    .collect();
-                let mesh = Mesh::new(&verts, &indices);
-                Ok(Manifold::from_mesh(mesh))
+                let data = ctx.cache.stl.load(&resolve_stl_path(path, include_paths))?;
+                let (verts, indices) = data.as_ref();
+                Ok(Manifold::from_mesh(Mesh::new(verts, indices)))
             }
         }
     }
@@ -1278,6 +2133,402 @@ impl Model3D {
     }
 }
 
+// ============================================================
+// decompose: 連結成分への分割
+// ============================================================
+
+/// 三角形の頂点共有をたどって連結成分ごとのメッシュに分ける。成分の順序は最初の三角形の出現順。
+fn split_connected_components(positions: &[f32], indices: &[u32]) -> Vec<(Vec<f32>, Vec<u32>)> {
+    let vertex_count = positions.len() / 3;
+    let mut parent: Vec<usize> = (0..vertex_count).collect();
+    fn find(parent: &mut [usize], mut v: usize) -> usize {
+        while parent[v] != v {
+            parent[v] = parent[parent[v]];
+            v = parent[v];
+        }
+        v
+    }
+    for tri in indices.chunks_exact(3) {
+        let root = find(&mut parent, tri[0] as usize);
+        for &v in &tri[1..] {
+            let r = find(&mut parent, v as usize);
+            parent[r] = root;
+        }
+    }
+
+    let mut component_of_root: std::collections::HashMap<usize, usize> =
+        std::collections::HashMap::new();
+    let mut components: Vec<(Vec<f32>, Vec<u32>)> = Vec::new();
+    let mut remap: Vec<Option<u32>> = vec![None; vertex_count];
+    for tri in indices.chunks_exact(3) {
+        let root = find(&mut parent, tri[0] as usize);
+        let ci = *component_of_root.entry(root).or_insert_with(|| {
+            components.push((Vec::new(), Vec::new()));
+            components.len() - 1
+        });
+        let (verts, idxs) = &mut components[ci];
+        for &v in tri {
+            let v = v as usize;
+            let new_index = *remap[v].get_or_insert_with(|| {
+                verts.extend_from_slice(&positions[v * 3..v * 3 + 3]);
+                (verts.len() / 3 - 1) as u32
+            });
+            idxs.push(new_index);
+        }
+    }
+    components
+}
+
+fn decompose_manifold(m: &Manifold) -> Vec<Manifold> {
+    let mesh = m.to_mesh();
+    split_connected_components(&mesh_positions(&mesh), &mesh.indices())
+        .into_iter()
+        .map(|(verts, indices)| Manifold::from_mesh(Mesh::new(&verts, &indices)))
+        .collect()
+}
+
+/// decompose(Shape, Components): Components を連結成分ごとの形状 __component(Shape, I) のリストに束縛する
+fn eval_decompose(
+    args: &[ScopedTerm],
+    ctx: &crate::term_processor::PredicateContext,
+) -> crate::term_processor::BuiltinPredicateResult {
+    let model = Model3D::from_term(&args[0]).map_err(|e| e.to_string())?;
+    let manifold = model
+        .evaluate(ctx.include_paths)
+        .map_err(|e| e.to_string())?;
+    let count = decompose_manifold(&manifold).len();
+    let components = (0..count)
+        .map(|i| {
+            struc(
+                FunctorTag::Component.to_string(),
+                vec![args[0].clone(), number_int(i as i64)],
+            )
+        })
+        .collect();
+    Ok(vec![(1, list(components, None))])
+}
+
+inventory::submit! {
+    crate::term_processor::BuiltinPredicate {
+        name: "decompose",
+        arity: 2,
+        resolve_args: true,
+        eval: eval_decompose,
+    }
+}
+
+// center_of_mass: 体積重心
+
+/// 閉じたメッシュの体積重心。原点と各三角形がなす符号付き四面体の重心を体積で重み付けして平均する
+fn mesh_center_of_mass(mesh: &Mesh) -> Option<[f64; 3]> {
+    let (volume, moment) = mesh_volume_moment(mesh);
+    (volume.abs() > f64::EPSILON).then(|| moment.map(|m| m / volume))
+}
+
+/// 原点と各三角形がなす符号付き四面体の体積の和と、体積で重み付けした重心の和
+pub(crate) fn mesh_volume_moment(mesh: &Mesh) -> (f64, [f64; 3]) {
+    let mut volume = 0.0;
+    let mut moment = [0.0; 3];
+    for [a, b, c] in mesh_triangles(mesh) {
+        let v = (a[0] * (b[1] * c[2] - b[2] * c[1]) - a[1] * (b[0] * c[2] - b[2] * c[0])
+            + a[2] * (b[0] * c[1] - b[1] * c[0]))
+            / 6.0;
+        volume += v;
+        for i in 0..3 {
+            moment[i] += v * (a[i] + b[i] + c[i]) / 4.0;
+        }
+    }
+    (volume, moment)
+}
+
+/// center_of_mass(Shape, X, Y, Z)
+fn eval_center_of_mass(
+    args: &[ScopedTerm],
+    ctx: &crate::term_processor::PredicateContext,
+) -> crate::term_processor::BuiltinPredicateResult {
+    let model = Model3D::from_term(&args[0]).map_err(|e| e.to_string())?;
+    let manifold = model
+        .evaluate(ctx.include_paths)
+        .map_err(|e| e.to_string())?;
+    let center = mesh_center_of_mass(&manifold.to_mesh()).ok_or_else(|| {
+        format!(
+            "center_of_mass: {} has no volume",
+            args[0].elided(ERROR_TERM_DEPTH)
+        )
+    })?;
+    Ok((0..3)
+        .map(|i| (i + 1, number(FixedPoint::from_f64(center[i]))))
+        .collect())
+}
+
+inventory::submit! {
+    crate::term_processor::BuiltinPredicate {
+        name: "center_of_mass",
+        arity: 4,
+        resolve_args: true,
+        eval: eval_center_of_mass,
+    }
+}
+
+// printable: 出力前の簡易チェック
+
+/// これ以下の体積の連結成分は体積を持たないとみなす
+const MIN_PRINTABLE_VOLUME: f64 = 1e-6;
+
+/// printable(Shape): 空でなく、どの連結成分も体積を持つときに成功する。
+/// 壁の厚みは測らないので、体積のある本体につながった薄い壁は通る
+fn eval_printable(
+    args: &[ScopedTerm],
+    ctx: &crate::term_processor::PredicateContext,
+) -> crate::term_processor::BuiltinPredicateResult {
+    let model = Model3D::from_term(&args[0]).map_err(|e| e.to_string())?;
+    let manifold = model
+        .evaluate(ctx.include_paths)
+        .map_err(|e| e.to_string())?;
+    if manifold.is_empty() {
+        return Err(format!(
+            "printable: {} is empty",
+            args[0].elided(ERROR_TERM_DEPTH)
+        ));
+    }
+    for (i, component) in decompose_manifold(&manifold).iter().enumerate() {
+        let (volume, _) = mesh_volume_moment(&component.to_mesh());
+        if volume <= MIN_PRINTABLE_VOLUME {
+            return Err(format!(
+                "printable: component {} of {} has no volume",
+                i,
+                args[0].elided(ERROR_TERM_DEPTH)
+            ));
+        }
+    }
+    Ok(vec![])
+}
+
+inventory::submit! {
+    crate::term_processor::BuiltinPredicate {
+        name: "printable",
+        arity: 1,
+        resolve_args: true,
+        eval: eval_printable,
+    }
+}
+
+// area / perimeter: 2D プロファイルの面積と周長
+
+/// 平坦な [x0, y0, x1, y1, ...] リングの符号付き面積（CCW で正、穴は負）
+fn ring_signed_area(ring: &[f64]) -> f64 {
+    let pts = flat_to_pairs(ring);
+    let n = pts.len();
+    (0..n)
+        .map(|i| {
+            let ((x0, y0), (x1, y1)) = (pts[i], pts[(i + 1) % n]);
+            x0 * y1 - x1 * y0
+        })
+        .sum::<f64>()
+        / 2.0
+}
+
+fn ring_perimeter(ring: &[f64]) -> f64 {
+    let pts = flat_to_pairs(ring);
+    let n = pts.len();
+    (0..n)
+        .map(|i| {
+            let ((x0, y0), (x1, y1)) = (pts[i], pts[(i + 1) % n]);
+            (x1 - x0).hypot(y1 - y0)
+        })
+        .sum()
+}
+
+fn profile_rings(term: &ScopedTerm, functor: &str) -> Result<Vec<Vec<f64>>, String> {
+    let profile = Model2D::from_term(term).map_err(|e| e.to_string())?;
+    polygon_rings_or_err(&profile, functor, DEFAULT_SEGMENTS).map_err(|e| e.to_string())
+}
+
+/// area(Profile, A)
+fn eval_area(
+    args: &[ScopedTerm],
+    _ctx: &crate::term_processor::PredicateContext,
+) -> crate::term_processor::BuiltinPredicateResult {
+    let rings = profile_rings(&args[0], "area")?;
+    let area: f64 = rings.iter().map(|r| ring_signed_area(r)).sum();
+    Ok(vec![(1, number(FixedPoint::from_f64(area.abs())))])
+}
+
+/// perimeter(Profile, P): 穴の周も含む
+fn eval_perimeter(
+    args: &[ScopedTerm],
+    _ctx: &crate::term_processor::PredicateContext,
+) -> crate::term_processor::BuiltinPredicateResult {
+    let rings = profile_rings(&args[0], "perimeter")?;
+    let perimeter: f64 = rings.iter().map(|r| ring_perimeter(r)).sum();
+    Ok(vec![(1, number(FixedPoint::from_f64(perimeter)))])
+}
+
+inventory::submit! {
+    crate::term_processor::BuiltinPredicate {
+        name: "area",
+        arity: 2,
+        resolve_args: true,
+        eval: eval_area,
+    }
+}
+inventory::submit! {
+    crate::term_processor::BuiltinPredicate {
+        name: "perimeter",
+        arity: 2,
+        resolve_args: true,
+        eval: eval_perimeter,
+    }
+}
+
+// convex_parts: 凸分解の近似
+
+/// 凸包との体積差がこの割合以下の部品は凸とみなす
+const CONVEX_VOLUME_TOLERANCE: f64 = 1e-3;
+/// 凸でない部品を二分する深さの上限
+const MAX_CONVEX_SPLIT_DEPTH: u32 = 4;
+
+/// piece（term はそれを表す式）が凸とみなせるまで外接箱の最長辺の中点で二分し、
+/// 各部品の凸包の式を parts に積む
+fn push_convex_parts(piece: &Manifold, term: ScopedTerm, depth: u32, parts: &mut Vec<ScopedTerm>) {
+    let mesh = piece.to_mesh();
+    let Some((min, max)) = mesh_bounds(&mesh) else {
+        return;
+    };
+    let (volume, _) = mesh_volume_moment(&mesh);
+    let (hull_volume, _) = mesh_volume_moment(&piece.hull().to_mesh());
+    if depth == 0 || hull_volume - volume <= CONVEX_VOLUME_TOLERANCE * hull_volume {
+        parts.push(struc(
+            FunctorTag::Hull.to_string(),
+            vec![term, struc(FunctorTag::Empty.to_string(), vec![])],
+        ));
+        return;
+    }
+
+    let extent = |i: usize| max[i] - min[i];
+    let axis = (0..3)
+        .max_by(|&a, &b| extent(a).total_cmp(&extent(b)))
+        .unwrap();
+    // 式に書ける精度に丸め、式と Manifold で同じ箱を使う
+    let fixed = |v: f64| FixedPoint::from_f64(v);
+    let pad = extent(axis).max(1.0);
+    let mid = fixed((min[axis] + max[axis]) / 2.0);
+    let lower: [FixedPoint; 3] = std::array::from_fn(|i| fixed((min[i] - pad).floor()));
+    let upper: [FixedPoint; 3] = std::array::from_fn(|i| fixed((max[i] + pad).ceil()));
+    for (lo, hi) in [(lower[axis], mid), (mid, upper[axis])] {
+        let mut from = lower;
+        let mut to = upper;
+        from[axis] = lo;
+        to[axis] = hi;
+        let size: [f64; 3] = std::array::from_fn(|i| to[i].to_f64() - from[i].to_f64());
+        let half = piece.intersection(&Manifold::cube(size[0], size[1], size[2]).translate(
+            from[0].to_f64(),
+            from[1].to_f64(),
+            from[2].to_f64(),
+        ));
+        let box_term = struc(
+            FunctorTag::Translate.to_string(),
+            vec![
+                struc(
+                    FunctorTag::Cube.to_string(),
+                    size.iter().map(|&v| number(fixed(v))).collect(),
+                ),
+                number(from[0]),
+                number(from[1]),
+                number(from[2]),
+            ],
+        );
+        let half_term = struc(
+            FunctorTag::Intersection.to_string(),
+            vec![term.clone(), box_term],
+        );
+        for (i, component) in decompose_manifold(&half).iter().enumerate() {
+            let component_term = struc(
+                FunctorTag::Component.to_string(),
+                vec![half_term.clone(), number_int(i as i64)],
+            );
+            push_convex_parts(component, component_term, depth - 1, parts);
+        }
+    }
+}
+
+/// convex_parts(Shape, Parts): Shape を一度評価して連結成分に分け、凸でない成分は二分を繰り返して、
+/// 各部品の凸包を Parts にする。各部品は元の部分を含むので、Parts の和は Shape を覆う
+fn eval_convex_parts(
+    args: &[ScopedTerm],
+    ctx: &crate::term_processor::PredicateContext,
+) -> crate::term_processor::BuiltinPredicateResult {
+    let model = Model3D::from_term(&args[0]).map_err(|e| e.to_string())?;
+    let manifold = model
+        .evaluate(ctx.include_paths)
+        .map_err(|e| e.to_string())?;
+    let mut parts = Vec::new();
+    for (i, component) in decompose_manifold(&manifold).iter().enumerate() {
+        let component_term = struc(
+            FunctorTag::Component.to_string(),
+            vec![args[0].clone(), number_int(i as i64)],
+        );
+        push_convex_parts(
+            component,
+            component_term,
+            MAX_CONVEX_SPLIT_DEPTH,
+            &mut parts,
+        );
+    }
+    Ok(vec![(1, list(parts, None))])
+}
+
+inventory::submit! {
+    crate::term_processor::BuiltinPredicate {
+        name: "convex_parts",
+        arity: 2,
+        resolve_args: true,
+        eval: eval_convex_parts,
+    }
+}
+
+// handed: 左右の鏡像ペア
+
+/// handed(Shape, Plane, Left, Right): Left は Shape そのまま、Right は Plane (xy / yz / xz) に関する鏡像。
+/// 和にはせず別々のモデルとして返す
+fn eval_handed(
+    args: &[ScopedTerm],
+    _ctx: &crate::term_processor::PredicateContext,
+) -> crate::term_processor::BuiltinPredicateResult {
+    let factors = match &args[1] {
+        Term::Struct { functor, args, .. } if args.is_empty() => match functor.as_str() {
+            "yz" => [-1, 1, 1],
+            "xz" => [1, -1, 1],
+            "xy" => [1, 1, -1],
+            _ => {
+                return Err(format!(
+                    "handed: unknown plane {}, expected xy, yz or xz",
+                    functor
+                ));
+            }
+        },
+        other => {
+            return Err(format!(
+                "handed: argument 1 must be a plane (xy, yz or xz), got {}",
+                other.elided(ERROR_TERM_DEPTH)
+            ));
+        }
+    };
+    let mut scale_args = vec![args[0].clone()];
+    scale_args.extend(factors.map(number_int));
+    let mirrored = struc(FunctorTag::Scale.to_string(), scale_args);
+    Ok(vec![(2, args[0].clone()), (3, mirrored)])
+}
+
+inventory::submit! {
+    crate::term_processor::BuiltinPredicate {
+        name: "handed",
+        arity: 4,
+        resolve_args: false,
+        eval: eval_handed,
+    }
+}
+
 // ============================================================
 // EvaluatedNode: raycastによるノード特定に使用
 // ============================================================
@@ -1332,7 +2583,12 @@ fn build_evaluated_node(
         }
         Model3D::Translate { model: e, .. }
         | Model3D::Scale { model: e, .. }
-        | Model3D::Rotate { model: e, .. } => {
+        | Model3D::Rotate { model: e, .. }
+        | Model3D::Warp { model: e, .. }
+        | Model3D::WithTolerance { model: e, .. }
+        | Model3D::WithSegments { model: e, .. }
+        | Model3D::Component { model: e, .. }
+        | Model3D::Center { model: e } => {
             vec![build_evaluated_node(e, include_paths)?]
         }
         _ => vec![],
@@ -1348,25 +2604,289 @@ fn build_evaluated_node(
     })
 }
 
+// ============================================================
+// 差分: 再描画時にメッシュを再利用できる部分木の特定
+// ============================================================
+
+/// 2つの Model3D の差分木。Changed の子は旧新で対応する子ノードの差分で、
+/// ノードの種類や自身のパラメータが変わった場合は空（部分木全体を再計算する）。
+#[derive(Debug, Clone, PartialEq)]
+pub enum ModelDiff {
+    Unchanged,
+    Changed(Vec<ModelDiff>),
+}
+
+fn hash_f64s<H: Hasher>(values: &[f64], state: &mut H) {
+    for v in values {
+        v.to_bits().hash(state);
+    }
+}
+
+impl Model3D {
+    /// 評価しなくても空になると分かる形状（empty とその変換・組み合わせ）
+    fn is_explicitly_empty(&self) -> bool {
+        match self {
+            Model3D::Empty => true,
+            Model3D::Union(a, b) => a.is_explicitly_empty() && b.is_explicitly_empty(),
+            Model3D::Intersection(a, b) => a.is_explicitly_empty() || b.is_explicitly_empty(),
+            Model3D::Difference(a, _) => a.is_explicitly_empty(),
+            Model3D::Translate { model, .. }
+            | Model3D::Scale { model, .. }
+            | Model3D::Rotate { model, .. }
+            | Model3D::Warp { model, .. }
+            | Model3D::WithTolerance { model, .. }
+            | Model3D::WithSegments { model, .. }
+            | Model3D::Component { model, .. }
+            | Model3D::Center { model } => model.is_explicitly_empty(),
+            _ => false,
+        }
+    }
+
+    /// プロファイル表示用のノード名（対応する functor 名）
+    fn label(&self) -> &'static str {
+        match self {
+            Model3D::Cube { .. } => "cube",
+            Model3D::Sphere { .. } => "sphere",
+            Model3D::Cylinder { .. } => "cylinder",
+            Model3D::Tetrahedron => "tetrahedron",
+            Model3D::Empty => "empty",
+            Model3D::Union(..) => "union",
+            Model3D::Difference(..) => "difference",
+            Model3D::Intersection(..) => "intersection",
+            Model3D::Hull(..) => "hull",
+            Model3D::Translate { .. } => "translate",
+            Model3D::Scale { .. } => "scale",
+            Model3D::Rotate { .. } => "rotate",
+            Model3D::Warp { .. } => "warp",
+            Model3D::LinearExtrude { .. } => "linear_extrude",
+            Model3D::ComplexExtrude { .. } => "complex_extrude",
+            Model3D::Revolve { .. } => "revolve",
+            Model3D::Stl { .. } => "stl",
+            Model3D::SweepExtrude { .. } => "sweep_extrude",
+            Model3D::Shared(..) => "let_shape",
+            Model3D::WithTolerance { .. } => "with_tolerance",
+            Model3D::WithSegments { .. } => "with_segments",
+            Model3D::Component { .. } => "component",
+            Model3D::Center { .. } => "center",
+        }
+    }
+
+    /// 評価はできるが意図しない可能性がある指定の警告（負の scale による鏡像化）。
+    /// 1 軸だけ -1 で残りが 1 の scale は handed などが書く意図的な鏡像なので警告しない
+    fn warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if let Model3D::Scale { x, y, z, .. } = self
+            && [x, y, z].iter().any(|f| **f < 0.0)
+            && !is_unit_reflection([*x, *y, *z])
+        {
+            warnings.push(format!(
+                "scale({}, {}, {}) has a negative factor and mirrors the shape",
+                x, y, z
+            ));
+        }
+        for child in self.children() {
+            warnings.extend(child.warnings());
+        }
+        warnings
+    }
+
+    /// 入れ子の translate を合計の移動量1回にまとめる。(translate でない最初の子, 移動量)
+    fn flatten_translate(&self) -> (&Model3D, [f64; 3]) {
+        let mut offset = [0.0; 3];
+        let mut node = self;
+        while let Model3D::Translate { model, x, y, z } = node {
+            offset = [offset[0] + x, offset[1] + y, offset[2] + z];
+            node = model;
+        }
+        (node, offset)
+    }
+
+    /// 入れ子の scale を倍率の積1回にまとめる。(scale でない最初の子, 倍率)
+    fn flatten_scale(&self) -> (&Model3D, [f64; 3]) {
+        let mut factor = [1.0; 3];
+        let mut node = self;
+        while let Model3D::Scale { model, x, y, z } = node {
+            factor = [factor[0] * x, factor[1] * y, factor[2] * z];
+            node = model;
+        }
+        (node, factor)
+    }
+
+    fn as_union(&self) -> Option<(&Model3D, &Model3D)> {
+        match self {
+            Model3D::Union(a, b) => Some((a, b)),
+            _ => None,
+        }
+    }
+
+    fn as_intersection(&self) -> Option<(&Model3D, &Model3D)> {
+        match self {
+            Model3D::Intersection(a, b) => Some((a, b)),
+            _ => None,
+        }
+    }
+
+    fn children(&self) -> Vec<&Model3D> {
+        match self {
+            Model3D::Union(a, b)
+            | Model3D::Difference(a, b)
+            | Model3D::Intersection(a, b)
+            | Model3D::Hull(a, b) => vec![a, b],
+            Model3D::Translate { model, .. }
+            | Model3D::Scale { model, .. }
+            | Model3D::Rotate { model, .. }
+            | Model3D::Warp { model, .. }
+            | Model3D::WithTolerance { model, .. }
+            | Model3D::WithSegments { model, .. }
+            | Model3D::Component { model, .. }
+            | Model3D::Center { model } => vec![model],
+            Model3D::Shared(shared) => vec![&shared.model],
+            _ => vec![],
+        }
+    }
+
+    /// 子を除いたノード自身の種類とパラメータのハッシュ
+    fn hash_node<H: Hasher>(&self, include_paths: &[PathBuf], state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Model3D::Cube { x, y, z }
+            | Model3D::Translate { x, y, z, .. }
+            | Model3D::Scale { x, y, z, .. }
+            | Model3D::Rotate { x, y, z, .. } => hash_f64s(&[*x, *y, *z], state),
+            Model3D::Sphere { radius, segments } => {
+                hash_f64s(&[*radius], state);
+                segments.hash(state);
+            }
+            Model3D::Warp { exprs, .. } => format!("{:?}", exprs).hash(state),
+            Model3D::Cylinder {
+                radius,
+                height,
+                segments,
+            } => {
+                hash_f64s(&[*radius, *height], state);
+                segments.hash(state);
+            }
+            Model3D::LinearExtrude { profile, height } => {
+                format!("{:?}", profile).hash(state);
+                hash_f64s(&[*height], state);
+            }
+            Model3D::ComplexExtrude {
+                profile,
+                height,
+                twist,
+                scale_x,
+                scale_y,
+            } => {
+                format!("{:?}", profile).hash(state);
+                hash_f64s(&[*height, *twist, *scale_x, *scale_y], state);
+            }
+            Model3D::Revolve {
+                profile,
+                degrees,
+                segments,
+            } => {
+                format!("{:?}", profile).hash(state);
+                hash_f64s(&[*degrees], state);
+                segments.hash(state);
+            }
+            Model3D::Stl { path } => {
+                path.hash(state);
+                // パスが同じでもファイルが更新されていれば別の形状とみなす
+                std::fs::metadata(resolve_stl_path(path, include_paths))
+                    .and_then(|m| m.modified())
+                    .ok()
+                    .hash(state);
+            }
+            Model3D::SweepExtrude { profile, path } => {
+                format!("{:?}", profile).hash(state);
+                format!("{:?}", path).hash(state);
+            }
+            Model3D::Shared(shared) => shared.name.hash(state),
+            Model3D::WithTolerance { tolerance, .. } => hash_f64s(&[*tolerance], state),
+            Model3D::WithSegments { segments, .. } => segments.hash(state),
+            Model3D::Component { index, .. } => index.hash(state),
+            Model3D::Tetrahedron
+            | Model3D::Empty
+            | Model3D::Center { .. }
+            | Model3D::Union(..)
+            | Model3D::Difference(..)
+            | Model3D::Intersection(..)
+            | Model3D::Hull(..) => {}
+        }
+    }
+
+    fn node_hash(&self, include_paths: &[PathBuf]) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.hash_node(include_paths, &mut hasher);
+        hasher.finish()
+    }
+
+    /// 構造ハッシュ。同じ構造・パラメータの式は同じ値になり、メッシュキャッシュのキーに使える。
+    /// stl はファイルの更新時刻も含めるため、評価時と同じ include_paths を渡す。
+    pub fn structural_hash(&self, include_paths: &[PathBuf]) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.hash_node(include_paths, &mut hasher);
+        for child in self.children() {
+            child.structural_hash(include_paths).hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+}
+
+pub fn diff_model(old: &Model3D, new: &Model3D, include_paths: &[PathBuf]) -> ModelDiff {
+    let old_children = old.children();
+    let new_children = new.children();
+    if old.node_hash(include_paths) != new.node_hash(include_paths)
+        || old_children.len() != new_children.len()
+    {
+        return ModelDiff::Changed(vec![]);
+    }
+    let child_diffs: Vec<ModelDiff> = old_children
+        .into_iter()
+        .zip(new_children)
+        .map(|(o, n)| diff_model(o, n, include_paths))
+        .collect();
+    if child_diffs.iter().all(|d| *d == ModelDiff::Unchanged) {
+        ModelDiff::Unchanged
+    } else {
+        ModelDiff::Changed(child_diffs)
+    }
+}
+
 // ============================================================
 // MeshGenerator: TermProcessor実装
 // ============================================================
 
+#[derive(Default)]
 pub struct MeshGenerator {
     pub include_paths: Vec<PathBuf>,
+    /// プレビューの再生成をまたいで stl の読み込み結果を使い回す
+    pub stl_cache: Arc<StlCache>,
 }
 
 impl<S> crate::term_processor::TermProcessor<S> for MeshGenerator {
-    type Output = (Mesh, Vec<EvaluatedNode>);
+    /// (メッシュ, 評価ノード, 変換時の警告)
+    type Output = (Mesh, Vec<EvaluatedNode>, Vec<String>);
     type Error = ConversionError;
 
     fn process(&self, terms: &[Term<S>]) -> Result<Self::Output, Self::Error> {
+        let in_element = |index: usize, e: ConversionError| {
+            if terms.len() > 1 {
+                ConversionError::InElement {
+                    index,
+                    source: Box::new(e),
+                }
+            } else {
+                e
+            }
+        };
         let exprs: Vec<Model3D> = terms
             .iter()
-            .filter_map(|t| match Model3D::from_term(t) {
+            .enumerate()
+            .filter_map(|(i, t)| match Model3D::from_term(t) {
                 Ok(e) => Some(Ok(e)),
                 Err(ConversionError::UnknownPrimitive(_)) => None,
-                Err(e) => Some(Err(e)),
+                Err(e) => Some(Err(in_element(i, e))),
             })
             .collect::<Result<Vec<_>, _>>()?;
 
@@ -1381,14 +2901,23 @@ impl<S> crate::term_processor::TermProcessor<S> for MeshGenerator {
             .map(|e| build_evaluated_node(e, &self.include_paths))
             .collect::<Result<Vec<_>, _>>()?;
 
+        let cache = EvalCache::with_stl_cache(self.stl_cache.clone());
         let manifold = exprs
             .iter()
-            .map(|e| e.evaluate(&self.include_paths))
+            .map(|e| e.evaluate_with_cache(&self.include_paths, &cache))
             .reduce(|acc, m| Ok(acc?.union(&m?)))
             .unwrap()?;
+        // manifold-rs は status() を公開していないため、空の結果を不正な形状として扱う。
+        // ただし empty で明示的に空を指定した場合はそのまま返す
+        if manifold.is_empty() && !exprs.iter().all(Model3D::is_explicitly_empty) {
+            return Err(ConversionError::InvalidGeometry(
+                "resulting shape is empty".to_string(),
+            ));
+        }
 
+        let warnings = exprs.iter().flat_map(Model3D::warnings).collect();
         let with_normals = manifold.calculate_normals(0, 30.0);
-        Ok((with_normals.to_mesh(), nodes))
+        Ok((with_normals.to_mesh(), nodes, warnings))
     }
 }
 
@@ -1397,16 +2926,72 @@ pub fn generate_mesh_and_tree_from_terms<S>(
     include_paths: &[PathBuf],
 ) -> Result<(Mesh, Vec<EvaluatedNode>), ConversionError> {
     use crate::term_processor::TermProcessor;
-    MeshGenerator {
+    let (mesh, nodes, _) = MeshGenerator {
         include_paths: include_paths.to_vec(),
+        ..MeshGenerator::default()
     }
-    .process(terms)
+    .process(terms)?;
+    Ok((mesh, nodes))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::parse::{number_int, string_lit, struc, var};
+    use crate::parse::{string_lit, var};
+    use crate::test_util::{binding, query_succeeds, run_query, solution};
+
+    #[test]
+    fn test_builtin_functor_list_matches_tags() {
+        let listed = crate::term_processor::all_builtin_functors();
+        for name in ["cube", "sphere", "union", "linear_extrude", "with_segments"] {
+            assert!(listed.iter().any(|(n, _)| *n == name), "{name} missing");
+        }
+        for (name, arities) in BUILTIN_FUNCTORS {
+            let tag = FunctorTag::from_str(name).unwrap();
+            assert_eq!(tag.to_string(), *name);
+            assert!(listed.contains(&(*name, *arities)));
+        }
+    }
+
+    #[test]
+    fn test_signatures_match_builtin_arities() {
+        for (name, arities) in BUILTIN_FUNCTORS {
+            for &arity in *arities {
+                assert!(
+                    functor_signature(name, arity).is_some(),
+                    "{name}/{arity} has no signature"
+                );
+            }
+        }
+        for s in FUNCTOR_SIGNATURES {
+            let arities = BUILTIN_FUNCTORS
+                .iter()
+                .find(|(n, _)| *n == s.name)
+                .map(|(_, a)| *a)
+                .unwrap();
+            assert!(arities.contains(&s.params.len()), "{}", s.name);
+        }
+    }
+
+    #[test]
+    fn test_signatures_match_from_struct_arities() {
+        let num = |_| number_int::<()>(3);
+        for name in ["cube", "sphere", "cylinder"] {
+            for arity in 0..5 {
+                let args: Vec<Term> = (0..arity).map(num).collect();
+                let accepted = Model3D::from_struct(name, &args, &[]).is_ok();
+                assert_eq!(
+                    accepted,
+                    functor_signature(name, arity).is_some(),
+                    "{name}/{arity}"
+                );
+            }
+        }
+        assert_eq!(
+            functor_signature("translate", 4),
+            Some(&["shape", "x", "y", "z"][..])
+        );
+    }
 
     #[test]
     fn test_cube_conversion() {
@@ -1425,13 +3010,116 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_constant_expressions_in_shape_args() {
+        let (t, _) = crate::parse::parse_single_term("cube(2 * 5, 10, 3 + 2)").unwrap();
+        match Model3D::from_term(&t).unwrap() {
+            Model3D::Cube { x, y, z } => assert_eq!((x, y, z), (10.0, 10.0, 5.0)),
+            other => panic!("Expected Cube, got {:?}", other),
+        }
+        let (t, _) = crate::parse::parse_single_term("cube(1 / 0, 1, 1)").unwrap();
+        assert!(matches!(
+            Model3D::from_term(&t),
+            Err(ConversionError::NonFiniteValue { arg_index: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn test_args_read_expression_and_range_midpoint() {
+        use crate::parse::Bound;
+        let range = Term::Var {
+            name: "W".into(),
+            scope: (),
+            default_value: None,
+            min: Some(Bound {
+                value: FixedPoint::from_int(2),
+                inclusive: true,
+            }),
+            max: Some(Bound {
+                value: FixedPoint::from_int(8),
+                inclusive: false,
+            }),
+            span: None,
+        };
+        let (scaled, _) = crate::parse::parse_single_term("H@1.5 * 4").unwrap();
+        let args = [range, scaled, number_int(16)];
+        let a = Args::new("cylinder", &args);
+        assert_eq!(a.f64(0).unwrap(), 5.0);
+        assert_eq!(a.f64(1).unwrap(), 6.0);
+        assert_eq!(a.u32(2).unwrap(), 16);
+        assert!(matches!(
+            Model3D::from_struct("cylinder", &args, &[]).unwrap(),
+            Model3D::Cylinder { radius, height, .. } if radius == 5.0 && height == 6.0
+        ));
+    }
+
+    #[test]
+    fn test_warp_conversion() {
+        let (t, _) =
+            crate::parse::parse_single_term("warp(cube(1, 1, 1), x, y, z + x * 0.1)").unwrap();
+        let Model3D::Warp { model, exprs } = Model3D::from_term(&t).unwrap() else {
+            panic!("Expected Warp");
+        };
+        assert!(matches!(*model, Model3D::Cube { .. }));
+        let p = [2.0, 3.0, 4.0];
+        assert_eq!(exprs.each_ref().map(|e| e.eval(p)), [2.0, 3.0, 4.2]);
+
+        let (t, _) = crate::parse::parse_single_term("warp(cube(1, 1, 1), x, y, w)").unwrap();
+        assert!(matches!(
+            Model3D::from_term(&t),
+            Err(ConversionError::TypeMismatch { arg_index: 3, .. })
+        ));
+    }
+
+    #[test]
+    fn test_warp_coordinates_survive_rewrite() {
+        let (resolved, _) = run_query(
+            "main :- warp(cube(1, 1, 1), x, y * 2, z + x * 0.1).",
+            "main.",
+        );
+        assert_eq!(resolved.len(), 1);
+        assert!(matches!(
+            Model3D::from_term(&resolved[0]).unwrap(),
+            Model3D::Warp { .. }
+        ));
+    }
+
+    #[test]
+    fn test_warp_coordinates_do_not_shadow_user_predicates() {
+        let (resolved, _) = run_query("x :- cube(1, 1, 1).\nmain :- warp(x, x, y, z).", "main.");
+        let Model3D::Warp { model, exprs } = Model3D::from_term(&resolved[0]).unwrap() else {
+            panic!("Expected Warp");
+        };
+        assert!(matches!(*model, Model3D::Cube { .. }));
+        assert!(matches!(exprs[0], WarpExpr::Coord(0)));
+
+        assert!(!query_succeeds("main :- x.", "main."));
+    }
+
+    #[test]
+    fn test_warp_shears_cube() {
+        let (t, _) =
+            crate::parse::parse_single_term("warp(cube(10, 10, 10), x, y, z + x * 0.1)").unwrap();
+        let mesh = Model3D::from_term(&t)
+            .unwrap()
+            .evaluate(&[])
+            .unwrap()
+            .to_mesh();
+        let original = Manifold::cube(10.0, 10.0, 10.0).to_mesh();
+        assert!(!mesh.indices().is_empty());
+        assert_ne!(mesh_positions(&mesh), mesh_positions(&original));
+        // せん断は体積を変えない
+        assert!((mesh_volume_moment(&mesh).0 - 1000.0).abs() < 1e-2);
+    }
+
     #[test]
     fn test_sphere_default_segments() {
         let term: Term = struc("sphere".into(), vec![number_int(5)]);
         let expr = Model3D::from_term(&term).unwrap();
         match expr {
-            Model3D::Sphere { radius } => {
+            Model3D::Sphere { radius, segments } => {
                 assert_eq!(radius, 5.0);
+                assert_eq!(segments, None);
             }
             _ => panic!("Expected Sphere"),
         }
@@ -1442,8 +3130,9 @@ mod tests {
         let term: Term = struc("sphere".into(), vec![number_int(5), number_int(16)]);
         let expr = Model3D::from_term(&term).unwrap();
         match expr {
-            Model3D::Sphere { radius } => {
+            Model3D::Sphere { radius, segments } => {
                 assert_eq!(radius, 5.0);
+                assert_eq!(segments, Some(16));
             }
             _ => panic!("Expected Sphere"),
         }
@@ -1454,7 +3143,7 @@ mod tests {
         let term: Term = struc("cylinder".into(), vec![number_int(3), number_int(10)]);
         let expr = Model3D::from_term(&term).unwrap();
         match expr {
-            Model3D::Cylinder { radius, height } => {
+            Model3D::Cylinder { radius, height, .. } => {
                 assert_eq!(radius, 3.0);
                 assert_eq!(height, 10.0);
             }
@@ -1498,6 +3187,139 @@ mod tests {
         }
     }
 
+    fn make_let_shape_term() -> Term {
+        let cube: Term = struc(
+            "cube".into(),
+            vec![number_int(1), number_int(1), number_int(1)],
+        );
+        let moved = struc(
+            "translate".into(),
+            vec![var("S".into()), number_int(5), number_int(0), number_int(0)],
+        );
+        let body = struc("union".into(), vec![var("S".into()), moved]);
+        struc("let_shape".into(), vec![var("S".into()), cube, body])
+    }
+
+    #[test]
+    fn test_let_shape_conversion() {
+        let expr = Model3D::from_term(&make_let_shape_term()).unwrap();
+        match expr {
+            Model3D::Union(a, b) => match (*a, *b) {
+                (Model3D::Shared(s1), Model3D::Translate { model, .. }) => match *model {
+                    Model3D::Shared(s2) => {
+                        assert!(Arc::ptr_eq(&s1, &s2));
+                        assert_eq!(s1.name, "S");
+                        assert!(matches!(s1.model, Model3D::Cube { .. }));
+                    }
+                    other => panic!("Expected Shared, got {:?}", other),
+                },
+                other => panic!("Expected (Shared, Translate), got {:?}", other),
+            },
+            other => panic!("Expected Union, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_let_shape_evaluates_once() {
+        let expr = Model3D::from_term(&make_let_shape_term()).unwrap();
+        let shared = match &expr {
+            Model3D::Union(a, _) => match a.as_ref() {
+                Model3D::Shared(s) => s.clone(),
+                other => panic!("Expected Shared, got {:?}", other),
+            },
+            other => panic!("Expected Union, got {:?}", other),
+        };
+        let mesh = expr.to_mesh(&[]).unwrap();
+        assert!(!mesh.vertices().is_empty());
+        assert_eq!(shared.evaluation_count(), 1);
+    }
+
+    #[test]
+    fn test_let_shape_name_must_be_variable() {
+        let cube: Term = struc(
+            "cube".into(),
+            vec![number_int(1), number_int(1), number_int(1)],
+        );
+        let term = struc("let_shape".into(), vec![number_int(1), cube.clone(), cube]);
+        let result = Model3D::from_term(&term);
+        assert!(matches!(result, Err(ConversionError::TypeMismatch { .. })));
+    }
+
+    #[test]
+    fn test_with_tolerance_conversion() {
+        let cube: Term = struc(
+            "cube".into(),
+            vec![number_int(1), number_int(1), number_int(1)],
+        );
+        let term = struc(
+            "with_tolerance".into(),
+            vec![
+                cube,
+                Term::Number {
+                    value: FixedPoint::from_hundredths(5),
+                },
+            ],
+        );
+        match Model3D::from_term(&term).unwrap() {
+            Model3D::WithTolerance { model, tolerance } => {
+                assert!(matches!(*model, Model3D::Cube { .. }));
+                assert_eq!(tolerance, 0.05);
+            }
+            other => panic!("Expected WithTolerance, got {:?}", other),
+        }
+
+        let cube: Term = struc(
+            "cube".into(),
+            vec![number_int(1), number_int(1), number_int(1)],
+        );
+        let zero = struc("with_tolerance".into(), vec![cube, number_int(0)]);
+        assert!(matches!(
+            Model3D::from_term(&zero),
+            Err(ConversionError::TypeMismatch { arg_index: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_with_tolerance_snaps_near_coincident_difference() {
+        // 10 と 10.01 の立方体の差は、tolerance 0.1 では完全に消える
+        let big: Term = struc(
+            "cube".into(),
+            vec![number_int(10), number_int(10), number_int(10)],
+        );
+        let slightly_bigger = Term::Number {
+            value: FixedPoint::from_hundredths(1001),
+        };
+        let other = struc(
+            "cube".into(),
+            vec![
+                slightly_bigger.clone(),
+                slightly_bigger.clone(),
+                slightly_bigger,
+            ],
+        );
+        let diff = struc("difference".into(), vec![other, big]);
+        let term = struc(
+            "with_tolerance".into(),
+            vec![
+                diff,
+                Term::Number {
+                    value: FixedPoint::from_hundredths(10),
+                },
+            ],
+        );
+        let manifold = Model3D::from_term(&term).unwrap().evaluate(&[]).unwrap();
+        let (volume, _) = mesh_volume_moment(&manifold.to_mesh());
+        assert!(volume.abs() < 1e-6, "volume {}", volume);
+    }
+
+    #[test]
+    fn test_snap_positions_merges_near_coincident_coordinates() {
+        let snapped = snap_positions(vec![10.0, 10.01, 9.97, 0.04], 0.1);
+        assert_eq!(snapped[0], snapped[1]);
+        assert_eq!(snapped[0], snapped[2]);
+        assert_eq!(snapped[3], 0.0);
+    }
+
     #[test]
     fn test_unbound_variable_error() {
         let term: Term = struc(
@@ -1505,7 +3327,42 @@ mod tests {
             vec![var("X".into()), number_int(1), number_int(1)],
         );
         let result = Model3D::from_term(&term);
-        assert!(matches!(result, Err(ConversionError::UnboundVariable(_))));
+        assert!(matches!(
+            result,
+            Err(ConversionError::UnboundVariable { .. })
+        ));
+    }
+
+    #[test]
+    fn test_unbound_variable_error_points_at_clause() {
+        use crate::term_rewrite::CadhrError;
+
+        let src = "a :- cube(1, 1, 1).\nb :- cube(X, 1, 1).";
+        let second_clause = src.find("b :-").unwrap()..src.len();
+        let (resolved, _) = run_query(src, "b.");
+        let Err(err) = generate_mesh_and_tree_from_terms(&resolved, &[]) else {
+            panic!("Expected UnboundVariable error");
+        };
+        let span = err
+            .span()
+            .expect("unbound variable error should carry a span");
+        assert!(second_clause.contains(&span.start));
+        assert_eq!(&src[span.start..span.end], "X");
+    }
+
+    #[test]
+    fn test_conversion_error_names_failing_element() {
+        use crate::term_rewrite::CadhrError;
+
+        let src = "main :- cube(1, 1, 1), cube(W, 1, 1), cube(2, 2, 2).";
+        let (resolved, _) = run_query(src, "main.");
+        let Err(err) = generate_mesh_and_tree_from_terms(&resolved, &[]) else {
+            panic!("Expected conversion error");
+        };
+        assert!(matches!(err, ConversionError::InElement { index: 1, .. }));
+        assert_eq!(err.to_string(), "element 1 of union: Unbound variable: W");
+        let span = err.span().expect("span of the inner error");
+        assert_eq!(&src[span.start..span.end], "W");
     }
 
     #[test]
@@ -1515,6 +3372,111 @@ mod tests {
         assert!(matches!(result, Err(ConversionError::ArityMismatch { .. })));
     }
 
+    #[test]
+    fn test_3d_shape_as_profile_is_type_mismatch() {
+        let cube: Term = struc(
+            "cube".into(),
+            vec![number_int(1), number_int(1), number_int(1)],
+        );
+        for (functor, extra) in [("linear_extrude", 5), ("revolve", 360)] {
+            let term = struc(functor.into(), vec![cube.clone(), number_int(extra)]);
+            let Err(err) = Model3D::from_term(&term) else {
+                panic!("Expected conversion error for {}", functor);
+            };
+            assert!(matches!(
+                &err,
+                ConversionError::TypeMismatch { functor: f, arg_index: 0, expected: "2D profile" }
+                    if f == functor
+            ));
+        }
+
+        let circle: Term = struc("circle".into(), vec![number_int(10)]);
+        let extrude = struc("linear_extrude".into(), vec![circle, number_int(5)]);
+        assert!(matches!(
+            Model3D::from_term(&extrude),
+            Ok(Model3D::LinearExtrude { .. })
+        ));
+    }
+
+    fn nested_transforms() -> Model3D {
+        let (t, _) = crate::parse::parse_single_term(
+            "translate(translate(translate(scale(scale(cube(1, 1, 1), 2, 3, 4), 0.5, 1, 2), 1, 0, 0), 0, 2, 0), 0, 0, 3)",
+        )
+        .unwrap();
+        Model3D::from_term(&t).unwrap()
+    }
+
+    #[test]
+    fn test_flatten_nested_transforms() {
+        let model = nested_transforms();
+        let (inner, offset) = model.flatten_translate();
+        assert_eq!(offset, [1.0, 2.0, 3.0]);
+        let (inner, factor) = inner.flatten_scale();
+        assert_eq!(factor, [1.0, 3.0, 8.0]);
+        assert!(matches!(inner, Model3D::Cube { .. }));
+    }
+
+    #[test]
+    fn test_nested_transforms_match_single_transform() {
+        let model = nested_transforms();
+        let single = Model3D::Translate {
+            model: Box::new(Model3D::Scale {
+                model: Box::new(Model3D::Cube {
+                    x: 1.0,
+                    y: 1.0,
+                    z: 1.0,
+                }),
+                x: 1.0,
+                y: 3.0,
+                z: 8.0,
+            }),
+            x: 1.0,
+            y: 2.0,
+            z: 3.0,
+        };
+        let bounds = |m: &Model3D| {
+            let mesh = m.evaluate(&[]).unwrap().to_mesh();
+            let volume = mesh_volume_moment(&mesh).0;
+            (mesh_bounds(&mesh).unwrap(), volume)
+        };
+        assert_eq!(bounds(&model), bounds(&single));
+    }
+
+    #[test]
+    fn test_zero_scale_factor_is_rejected() {
+        let (t, _) = crate::parse::parse_single_term("scale(cube(1, 1, 1), 0, 1, 1)").unwrap();
+        let Err(err) = Model3D::from_term(&t) else {
+            panic!("Expected conversion error");
+        };
+        assert_eq!(
+            err.to_string(),
+            "Invalid value for scale arg 1: expected non-zero scale factor, got 0"
+        );
+    }
+
+    #[test]
+    fn test_negative_scale_factor_warns() {
+        let warnings = |src: &str| {
+            let (t, _) = crate::parse::parse_single_term(src).unwrap();
+            Model3D::from_term(&t).unwrap().warnings()
+        };
+        assert_eq!(
+            warnings("translate(scale(cube(1, 1, 1), -2, 1, 1), 1, 0, 0)"),
+            ["scale(-2, 1, 1) has a negative factor and mirrors the shape"]
+        );
+        assert!(warnings("scale(cube(1, 1, 1), 2, 1, 1)").is_empty());
+        // 1 軸だけの -1 倍は意図的な鏡像とみなす
+        assert!(warnings("scale(cube(1, 1, 1), 1, -1, 1)").is_empty());
+        assert_eq!(warnings("scale(cube(1, 1, 1), -1, -1, 1)").len(), 1);
+    }
+
+    #[test]
+    fn test_handed_mirror_does_not_warn() {
+        let (_, env) = run_query("", "handed(cube(1, 2, 3), yz, L, R).");
+        let right = Model3D::from_term(&binding(&env, "R")).unwrap();
+        assert!(right.warnings().is_empty());
+    }
+
     #[test]
     fn test_unknown_primitive() {
         let term: Term = struc("unknown_shape".into(), vec![number_int(1)]);
@@ -1541,6 +3503,28 @@ mod tests {
         assert!(matches!(expr, Model3D::Difference(_, _)));
     }
 
+    #[test]
+    fn test_evaluate_profiled_nested_csg() {
+        let (resolved, _) = run_query(
+            "main :- difference(union(cube(1, 1, 1), translate(cube(1, 1, 1), 2, 0, 0)), sphere(1)).",
+            "main.",
+        );
+        let model = Model3D::from_term(&resolved[0]).unwrap();
+
+        let profile = EvalProfile::default();
+        model.evaluate_profiled(&[], &profile).unwrap();
+        let labels: Vec<String> = profile
+            .take_profile()
+            .into_iter()
+            .map(|(label, _)| label)
+            .collect();
+        assert_eq!(
+            labels,
+            ["cube", "cube", "translate", "union", "sphere", "difference"]
+        );
+        assert!(profile.take_profile().is_empty());
+    }
+
     #[test]
     fn test_operator_union() {
         use crate::parse::ArithOp;
@@ -1631,7 +3615,19 @@ mod tests {
         let div_term = arith_expr(ArithOp::Div, cube, sphere);
 
         let result = Model3D::from_term(&div_term);
-        assert!(matches!(result, Err(ConversionError::UnknownPrimitive(_))));
+        assert!(matches!(result, Err(ConversionError::ShapeDivision { .. })));
+    }
+
+    #[test]
+    fn test_shape_division_error_names_operands_and_alternatives() {
+        let (t, _) = crate::parse::parse_single_term("cube(1, 1, 1) / sphere(2)").unwrap();
+        let Err(err) = Model3D::from_term(&t) else {
+            panic!("expected division error");
+        };
+        let message = err.to_string();
+        assert!(message.contains("cube(1, 1, 1) / sphere(2)"), "{message}");
+        assert!(message.contains("difference"), "{message}");
+        assert!(message.contains("intersection"), "{message}");
     }
 
     fn make_polygon_term(pts: Vec<(i64, i64)>) -> Term {
@@ -1659,7 +3655,7 @@ mod tests {
         let term: Term = struc("circle".into(), vec![number_int(5)]);
         let expr = Model2D::from_term(&term).unwrap();
         match expr {
-            Model2D::SketchXY(Plane2D::Circle { radius }) => {
+            Model2D::SketchXY(Plane2D::Circle { radius, .. }) => {
                 assert_eq!(radius, 5.0);
             }
             _ => panic!("Expected SketchXY(Circle)"),
@@ -1676,24 +3672,103 @@ mod tests {
                 assert!(matches!(profile, Model2D::SketchXY(Plane2D::Sketch { .. })));
                 assert_eq!(height, 3.0);
             }
-            _ => panic!("Expected LinearExtrude"),
+            _ => panic!("Expected LinearExtrude"),
+        }
+    }
+
+    #[test]
+    fn test_revolve_circle() {
+        let circle: Term = struc("circle".into(), vec![number_int(5)]);
+        let term = struc("revolve".into(), vec![circle, number_int(360)]);
+        let expr = Model3D::from_term(&term).unwrap();
+        match expr {
+            Model3D::Revolve {
+                profile, degrees, ..
+            } => {
+                assert!(matches!(profile, Model2D::SketchXY(Plane2D::Circle { .. })));
+                assert_eq!(degrees, 360.0);
+            }
+            _ => panic!("Expected Revolve"),
         }
     }
 
+    fn make_annulus_term() -> Term {
+        let outer: Term = struc("circle".into(), vec![number_int(10)]);
+        let inner = struc("circle".into(), vec![number_int(5)]);
+        struc("difference".into(), vec![outer, inner])
+    }
+
     #[test]
-    fn test_revolve_circle() {
-        let circle: Term = struc("circle".into(), vec![number_int(5)]);
-        let term = struc("revolve".into(), vec![circle, number_int(360)]);
+    fn test_revolve_csg_profile_conversion() {
+        let term = struc("revolve".into(), vec![make_annulus_term(), number_int(360)]);
         let expr = Model3D::from_term(&term).unwrap();
         match expr {
-            Model3D::Revolve { profile, degrees } => {
-                assert!(matches!(profile, Model2D::SketchXY(Plane2D::Circle { .. })));
+            Model3D::Revolve {
+                profile, degrees, ..
+            } => {
+                assert!(matches!(profile, Model2D::Difference(_, _)));
                 assert_eq!(degrees, 360.0);
             }
             _ => panic!("Expected Revolve"),
         }
     }
 
+    #[test]
+    fn test_revolve_csg_profile_evaluate() {
+        let term = struc("revolve".into(), vec![make_annulus_term(), number_int(360)]);
+        let expr = Model3D::from_term(&term).unwrap();
+        let Model3D::Revolve { profile, .. } = &expr else {
+            panic!("Expected Revolve");
+        };
+        // 外周と穴の2リングに評価されてからrevolveされる
+        let rings = profile.to_polygon_rings_with(DEFAULT_SEGMENTS).unwrap();
+        assert_eq!(rings.len(), 2);
+        let mesh = expr.to_mesh(&[]).unwrap();
+        assert!(!mesh.vertices().is_empty());
+    }
+
+    #[test]
+    fn test_notched_circle_extrude_has_less_volume() {
+        let volume = |src: &str| {
+            let (t, _) = crate::parse::parse_single_term(src).unwrap();
+            let mesh = Model3D::from_term(&t).unwrap().to_mesh(&[]).unwrap();
+            mesh_volume_moment(&mesh).0
+        };
+        let plain = volume("linear_extrude(circle(10), 5)");
+        let notched = volume(
+            "linear_extrude(circle(10) - sketchXY([p(5, -2), p(15, -2), p(15, 2), p(5, 2)]), 5)",
+        );
+        // 切り欠きは x=5..10, 幅 4 の部分: 体積はおよそ 4 * 5 * 5 = 100 減る
+        assert!(
+            plain - notched > 80.0,
+            "plain {} notched {}",
+            plain,
+            notched
+        );
+    }
+
+    #[test]
+    fn test_infix_2d_difference_extrudes_to_tube() {
+        let (t, _) =
+            crate::parse::parse_single_term("linear_extrude(circle(10) - circle(5), 20)").unwrap();
+        let expr = Model3D::from_term(&t).unwrap();
+        let Model3D::LinearExtrude { profile, height } = &expr else {
+            panic!("Expected LinearExtrude, got {:?}", expr);
+        };
+        assert!(matches!(profile, Model2D::Difference(_, _)));
+        assert_eq!(*height, 20.0);
+        // 外周と穴の2リングのプロファイルになる
+        assert_eq!(
+            profile
+                .to_polygon_rings_with(DEFAULT_SEGMENTS)
+                .unwrap()
+                .len(),
+            2
+        );
+        let mesh = expr.to_mesh(&[]).unwrap();
+        assert!(!mesh.vertices().is_empty());
+    }
+
     #[test]
     fn test_extrude_circle() {
         let circle: Term = struc("circle".into(), vec![number_int(5)]);
@@ -1733,7 +3808,7 @@ mod tests {
         let poly_b = make_polygon_term(vec![(1, 1), (3, 1), (3, 3), (1, 3)]);
         let union_term = struc("union".into(), vec![poly_a, poly_b]);
         let expr = Model2D::from_term(&union_term).unwrap();
-        let rings = expr.to_polygon_rings();
+        let rings = expr.to_polygon_rings_with(DEFAULT_SEGMENTS);
         assert!(
             rings.is_some(),
             "union of polygons should produce polygon rings"
@@ -1748,7 +3823,7 @@ mod tests {
         let poly_b = make_polygon_term(vec![(1, 1), (3, 1), (3, 3), (1, 3)]);
         let diff_term = struc("difference".into(), vec![poly_a, poly_b]);
         let expr = Model2D::from_term(&diff_term).unwrap();
-        let rings = expr.to_polygon_rings();
+        let rings = expr.to_polygon_rings_with(DEFAULT_SEGMENTS);
         assert!(
             rings.is_some(),
             "difference of polygons should produce polygon rings"
@@ -1771,11 +3846,11 @@ mod tests {
 
         let rings_ccw = Model2D::from_term(&diff_ccw)
             .unwrap()
-            .to_polygon_rings()
+            .to_polygon_rings_with(DEFAULT_SEGMENTS)
             .unwrap();
         let rings_cw = Model2D::from_term(&diff_cw)
             .unwrap()
-            .to_polygon_rings()
+            .to_polygon_rings_with(DEFAULT_SEGMENTS)
             .unwrap();
 
         // 両方リングを持つこと
@@ -1791,6 +3866,26 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_clockwise_square_extrudes_with_positive_volume() {
+        let square_cw = make_polygon_term(vec![(0, 0), (0, 10), (10, 10), (10, 0)]);
+        let profile = Model2D::from_term(&square_cw).unwrap();
+        let ring = &profile.to_polygon_rings_with(DEFAULT_SEGMENTS).unwrap()[0];
+        let signed_area: f64 = (0..ring.len() / 2)
+            .map(|i| {
+                let j = (i + 1) % (ring.len() / 2);
+                ring[2 * i] * ring[2 * j + 1] - ring[2 * j] * ring[2 * i + 1]
+            })
+            .sum::<f64>()
+            / 2.0;
+        assert_eq!(signed_area, 100.0);
+
+        let term = struc("linear_extrude".into(), vec![square_cw, number_int(5)]);
+        let mesh = Model3D::from_term(&term).unwrap().to_mesh(&[]).unwrap();
+        let volume = mesh_volume_moment(&mesh).0;
+        assert!((volume - 500.0).abs() < 1e-3, "volume = {volume}");
+    }
+
     #[test]
     fn test_polygon_operator_plus() {
         use crate::parse::ArithOp;
@@ -1801,7 +3896,7 @@ mod tests {
         let add_term = arith_expr(ArithOp::Add, poly_a, poly_b);
         let expr = Model2D::from_term(&add_term).unwrap();
         assert!(matches!(expr, Model2D::Union(_, _)));
-        let rings = expr.to_polygon_rings();
+        let rings = expr.to_polygon_rings_with(DEFAULT_SEGMENTS);
         assert!(rings.is_some());
     }
 
@@ -1891,6 +3986,92 @@ mod tests {
         std::fs::remove_dir_all(&dir).ok();
     }
 
+    #[test]
+    fn test_stl_cache_reuses_until_modified() {
+        use stl_io::{Normal, Triangle, Vertex};
+
+        let dir = tempfile::tempdir().unwrap();
+        let stl_path = dir.path().join("part.stl");
+        let tri = Triangle {
+            normal: Normal::new([0.0, 0.0, 1.0]),
+            vertices: [
+                Vertex::new([0.0, 0.0, 0.0]),
+                Vertex::new([1.0, 0.0, 0.0]),
+                Vertex::new([0.0, 1.0, 0.0]),
+            ],
+        };
+        let file = std::fs::File::create(&stl_path).unwrap();
+        stl_io::write_stl(&mut &file, [tri].iter()).unwrap();
+
+        let cache = StlCache::default();
+        let first = cache.load(&stl_path).unwrap();
+        let second = cache.load(&stl_path).unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+
+        let later = std::fs::metadata(&stl_path).unwrap().modified().unwrap()
+            + std::time::Duration::from_secs(10);
+        file.set_modified(later).unwrap();
+        let reloaded = cache.load(&stl_path).unwrap();
+        assert!(!Arc::ptr_eq(&first, &reloaded));
+        assert_eq!(first, reloaded);
+    }
+
+    fn write_triangle_stl(path: &Path) {
+        use stl_io::{Normal, Triangle, Vertex};
+
+        let tri = Triangle {
+            normal: Normal::new([0.0, 0.0, 1.0]),
+            vertices: [
+                Vertex::new([0.0, 0.0, 0.0]),
+                Vertex::new([1.0, 0.0, 0.0]),
+                Vertex::new([0.0, 1.0, 0.0]),
+            ],
+        };
+        let mut file = std::fs::File::create(path).unwrap();
+        stl_io::write_stl(&mut file, [tri].iter()).unwrap();
+    }
+
+    #[test]
+    fn test_stl_cache_evicts_least_recently_used() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths: Vec<PathBuf> = (0..=STL_CACHE_CAPACITY)
+            .map(|i| dir.path().join(format!("part{}.stl", i)))
+            .collect();
+        paths.iter().for_each(|p| write_triangle_stl(p));
+
+        let cache = StlCache::default();
+        let first = cache.load(&paths[0]).unwrap();
+        let second = cache.load(&paths[1]).unwrap();
+        for path in &paths[2..STL_CACHE_CAPACITY] {
+            cache.load(path).unwrap();
+        }
+        // paths[0] を使い直したので、次に捨てられるのは paths[1]
+        assert!(Arc::ptr_eq(&first, &cache.load(&paths[0]).unwrap()));
+        cache.load(&paths[STL_CACHE_CAPACITY]).unwrap();
+        assert_eq!(cache.entries().len(), STL_CACHE_CAPACITY);
+        assert!(Arc::ptr_eq(&first, &cache.load(&paths[0]).unwrap()));
+        assert!(!Arc::ptr_eq(&second, &cache.load(&paths[1]).unwrap()));
+    }
+
+    #[test]
+    fn test_stl_cache_survives_poisoned_lock() {
+        let dir = tempfile::tempdir().unwrap();
+        let stl_path = dir.path().join("part.stl");
+        write_triangle_stl(&stl_path);
+
+        let cache = StlCache::default();
+        let first = cache.load(&stl_path).unwrap();
+        std::thread::scope(|s| {
+            let poisoner = s.spawn(|| {
+                let _entries = cache.entries.lock().unwrap();
+                panic!("poison the stl cache");
+            });
+            assert!(poisoner.join().is_err());
+        });
+        assert!(cache.entries.is_poisoned());
+        assert!(Arc::ptr_eq(&first, &cache.load(&stl_path).unwrap()));
+    }
+
     #[test]
     fn test_extract_control_points() {
         let cube: Term = struc(
@@ -1964,6 +4145,60 @@ mod tests {
         assert!(mesh.vertices().len() > 0);
     }
 
+    #[test]
+    fn test_staircase_from_findall_and_union_list() {
+        let (resolved, _) = run_query(
+            "stairs(S) :- findall(translate(cube(10, 10, 2), 0, Y, Y), member(Y, [0, 2, 4, 6]), Steps), union_list(Steps, S).",
+            "stairs(S), S.",
+        );
+        let (mesh, _) = generate_mesh_and_tree_from_terms(&resolved, &[]).unwrap();
+        let (min, max) = mesh_bounds(&mesh).unwrap();
+        let step_height = 2.0;
+        assert_eq!(((max[2] - min[2]) / step_height).round(), 4.0);
+        assert_eq!(max[1] - min[1], 16.0);
+    }
+
+    #[test]
+    fn test_empty_result_is_invalid_geometry() {
+        let (resolved, _) = run_query(
+            "main :- difference(cube(1, 1, 1), translate(cube(3, 3, 3), -1, -1, -1)).",
+            "main.",
+        );
+        let Err(err) = generate_mesh_and_tree_from_terms(&resolved, &[]) else {
+            panic!("Expected InvalidGeometry error");
+        };
+        assert!(matches!(err, ConversionError::InvalidGeometry(_)));
+    }
+
+    #[test]
+    fn test_explicit_empty_is_not_invalid_geometry() {
+        let (resolved, _) = run_query("main :- translate(empty, 1, 0, 0).", "main.");
+        let Ok((mesh, _)) = generate_mesh_and_tree_from_terms(&resolved, &[]) else {
+            panic!("Expected an empty mesh");
+        };
+        assert!(mesh.indices().is_empty());
+    }
+
+    #[test]
+    fn test_empty_is_union_identity() {
+        let (t, _) = crate::parse::parse_single_term("union(cube(1, 1, 1), empty)").unwrap();
+        let expr = Model3D::from_term(&t).unwrap();
+        let with_empty = mesh_volume_moment(&expr.evaluate(&[]).unwrap().to_mesh()).0;
+        let cube = mesh_volume_moment(&Manifold::cube(1.0, 1.0, 1.0).to_mesh()).0;
+        assert!((with_empty - cube).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_empty_conversion() {
+        let (t, _) = crate::parse::parse_single_term("cube(1, 1, 1) - empty").unwrap();
+        let expr = Model3D::from_term(&t).unwrap();
+        assert!(matches!(expr, Model3D::Difference(_, ref b) if matches!(**b, Model3D::Empty)));
+        assert!(!expr.is_explicitly_empty());
+        let (t, _) =
+            crate::parse::parse_single_term("rotate(empty * cube(1, 1, 1), 0, 0, 45)").unwrap();
+        assert!(Model3D::from_term(&t).unwrap().is_explicitly_empty());
+    }
+
     #[test]
     fn test_control_shared_var_without_default() {
         use crate::parse::{database, query as parse_query};
@@ -2071,6 +4306,232 @@ mod tests {
         eprintln!("case3: {:?}", resolved3);
     }
 
+    #[test]
+    fn test_split_connected_components() {
+        // 頂点を共有しない2つの三角形と、辺を共有する2つの三角形
+        let positions = [
+            0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, //
+            5.0, 0.0, 0.0, 6.0, 0.0, 0.0, 5.0, 1.0, 0.0, //
+            1.0, 1.0, 0.0,
+        ];
+        let components = split_connected_components(&positions, &[0, 1, 2, 3, 4, 5, 1, 6, 2]);
+        assert_eq!(components.len(), 2);
+        assert_eq!(components[0].1, vec![0, 1, 2, 1, 3, 2]);
+        assert_eq!(components[0].0.len(), 4 * 3);
+        assert_eq!(components[1].1, vec![0, 1, 2]);
+        assert_eq!(&components[1].0[..3], &[5.0, 0.0, 0.0]);
+
+        let single = split_connected_components(&positions, &[0, 1, 2, 1, 6, 2]);
+        assert_eq!(single.len(), 1);
+    }
+
+    #[test]
+    fn test_mesh_center_of_mass_of_tetrahedron() {
+        let verts = [0.0, 0.0, 0.0, 4.0, 0.0, 0.0, 0.0, 4.0, 0.0, 0.0, 0.0, 4.0];
+        let indices = [0, 2, 1, 0, 1, 3, 0, 3, 2, 1, 2, 3];
+        let center = mesh_center_of_mass(&Mesh::new(&verts, &indices)).unwrap();
+        for c in center {
+            assert!((c - 1.0).abs() < 1e-9, "{:?}", center);
+        }
+        assert!(mesh_center_of_mass(&Mesh::new(&[], &[])).is_none());
+    }
+
+    fn profile_measure(predicate: &str, profile_src: &str) -> f64 {
+        let (_, env) = run_query("", &format!("{}({}, V).", predicate, profile_src));
+        match binding(&env, "V") {
+            Term::Number { value } => value.to_f64(),
+            other => panic!("Expected number, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_area_and_perimeter_of_polygon() {
+        let triangle = "sketchXY([p(0, 0), p(4, 0), p(4, 3)])";
+        assert_eq!(profile_measure("area", triangle), 6.0);
+        assert_eq!(profile_measure("perimeter", triangle), 12.0);
+        // 時計回りでも面積は正
+        let cw = "sketchXY([p(0, 0), p(0, 2), p(5, 2), p(5, 0)])";
+        assert_eq!(profile_measure("area", cw), 10.0);
+    }
+
+    #[test]
+    fn test_area_of_circle_approximates_pi_r_squared() {
+        let area = profile_measure("area", "circle(10)");
+        let expected = std::f64::consts::PI * 100.0;
+        assert!((area - expected).abs() / expected < 0.01, "{}", area);
+        let perimeter = profile_measure("perimeter", "circle(10)");
+        let expected = 20.0 * std::f64::consts::PI;
+        assert!(
+            (perimeter - expected).abs() / expected < 0.01,
+            "{}",
+            perimeter
+        );
+    }
+
+    #[test]
+    fn test_handed_pair_mirrors_across_plane() {
+        let (_, env) = run_query("", "handed(translate(cube(1, 2, 3), 1, 0, 0), yz, L, R).");
+        let bounds = |name: &str| {
+            let mesh = Model3D::from_term(&binding(&env, name))
+                .unwrap()
+                .evaluate(&[])
+                .unwrap()
+                .to_mesh();
+            mesh_bounds(&mesh).unwrap()
+        };
+        let (left_min, left_max) = bounds("L");
+        let (right_min, right_max) = bounds("R");
+        assert_eq!((left_min[0], left_max[0]), (1.0, 2.0));
+        assert_eq!((right_min[0], right_max[0]), (-2.0, -1.0));
+        assert_eq!(&left_min[1..], &right_min[1..]);
+        assert_eq!(&left_max[1..], &right_max[1..]);
+    }
+
+    #[test]
+    fn test_center_of_mass_predicate() {
+        assert_eq!(
+            solution(
+                "",
+                "center_of_mass(translate(cube(2, 2, 2), -1, -1, -1), X, Y, Z)."
+            ),
+            "X = 0, Y = 0, Z = 0"
+        );
+        // 2x2x2 と、x方向に並べた 2x2x2 の和: 重心は x=2
+        assert_eq!(
+            solution(
+                "",
+                "center_of_mass(union(cube(2, 2, 2), translate(cube(2, 2, 2), 2, 0, 0)), X, Y, Z)."
+            ),
+            "X = 2, Y = 1, Z = 1"
+        );
+    }
+
+    #[test]
+    fn test_printable() {
+        assert!(query_succeeds("", "printable(cube(1, 1, 1))."));
+        assert!(!query_succeeds(
+            "",
+            "printable(difference(cube(1, 1, 1), cube(2, 2, 2)))."
+        ));
+    }
+
+    fn decompose_count(shape_src: &str) -> usize {
+        let (resolved, env) = run_query("", &format!("decompose({}, C).", shape_src));
+        assert!(resolved.is_empty());
+        match binding(&env, "C") {
+            Term::List { items, tail: None } => {
+                for item in &items {
+                    assert!(
+                        matches!(item, Term::Struct { functor, .. } if functor == "__component")
+                    );
+                }
+                items.len()
+            }
+            other => panic!("Expected list of components, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_decompose_disjoint_cubes() {
+        assert_eq!(
+            decompose_count("union(cube(1, 1, 1), translate(cube(1, 1, 1), 5, 0, 0))"),
+            2
+        );
+    }
+
+    #[test]
+    fn test_decompose_single_cube() {
+        assert_eq!(decompose_count("cube(1, 1, 1)"), 1);
+    }
+
+    #[test]
+    fn test_user_component_predicate_is_not_shadowed() {
+        assert_eq!(
+            solution("component(bracket, 2).", "component(bracket, N)."),
+            "N = 2"
+        );
+    }
+
+    #[test]
+    fn test_components_share_one_decomposition() {
+        let (shape, _) = crate::parse::parse_single_term(
+            "union(cube(1, 1, 1), translate(cube(1, 1, 1), 5, 0, 0))",
+        )
+        .unwrap();
+        let component = |i| struc("__component".into(), vec![shape.clone(), number_int(i)]);
+        let t = struc("union".into(), vec![component(0), component(1)]);
+        let cache = EvalCache::default();
+        let mesh = Model3D::from_term(&t)
+            .unwrap()
+            .evaluate_with_cache(&[], &cache)
+            .unwrap()
+            .to_mesh();
+        assert_eq!(cache.components.borrow().len(), 1);
+        assert!((mesh_volume_moment(&mesh).0 - 2.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_decompose_resolves_stl_in_include_paths() {
+        use crate::parse::{database, query as parse_query};
+        use crate::term_rewrite::{ExecuteOptions, execute_with_options};
+        use stl_io::{Normal, Triangle, Vertex};
+
+        let dir = tempfile::tempdir().unwrap();
+        let v = [
+            Vertex::new([0.0, 0.0, 0.0]),
+            Vertex::new([1.0, 0.0, 0.0]),
+            Vertex::new([0.0, 1.0, 0.0]),
+            Vertex::new([0.0, 0.0, 1.0]),
+        ];
+        let tris = [[0, 2, 1], [0, 1, 3], [0, 3, 2], [1, 2, 3]].map(|[a, b, c]| Triangle {
+            normal: Normal::new([0.0, 0.0, 0.0]),
+            vertices: [v[a], v[b], v[c]],
+        });
+        let mut file = std::fs::File::create(dir.path().join("part.stl")).unwrap();
+        stl_io::write_stl(&mut file, tris.iter()).unwrap();
+
+        let mut db = database("").unwrap();
+        let (_, q) = parse_query("decompose(stl(\"part.stl\"), C).").unwrap();
+        let options = ExecuteOptions {
+            include_paths: vec![dir.path().to_path_buf()],
+            ..ExecuteOptions::default()
+        };
+        let execution = execute_with_options(&mut db, q, options).unwrap();
+        assert!(execution.terms.is_empty());
+    }
+
+    #[test]
+    fn test_convex_parts_of_l_shape() {
+        let shape_src = "union(cube(2, 1, 1), cube(1, 2, 1))";
+        let (_, env) = run_query("", &format!("convex_parts({}, P).", shape_src));
+        let Term::List { items, tail: None } = binding(&env, "P") else {
+            panic!("Expected list of parts");
+        };
+        assert!(items.len() >= 2);
+
+        let (shape, _) = crate::parse::parse_single_term(shape_src).unwrap();
+        let original = Model3D::from_term(&shape).unwrap().evaluate(&[]).unwrap();
+        let covered = items
+            .iter()
+            .map(|part| Model3D::from_term(part).unwrap().evaluate(&[]).unwrap())
+            .fold(Manifold::empty(), |acc, m| acc.union(&m));
+        assert!(original.difference(&covered).is_empty());
+    }
+
+    #[test]
+    fn test_convex_parts_splits_evaluated_geometry() {
+        let part_count = |shape_src: &str| {
+            let (_, env) = run_query("", &format!("convex_parts({}, P).", shape_src));
+            let Term::List { items, tail: None } = binding(&env, "P") else {
+                panic!("Expected list of parts");
+            };
+            items.len()
+        };
+        // 和を使わずに書いた L 字も分割される
+        assert!(part_count("difference(cube(2, 2, 1), translate(cube(1, 1, 1), 1, 1, 0))") >= 2);
+        assert_eq!(part_count("cube(2, 1, 1)"), 1);
+    }
+
     #[test]
     fn test_apply_var_overrides() {
         use crate::parse::{database, query as parse_query};
@@ -2293,4 +4754,273 @@ mod tests {
         let mesh = expr.to_mesh(&[]).unwrap();
         assert!(mesh.vertices().len() > 0);
     }
+
+    // ===== diff_model =====
+
+    fn union_of_cubes(sizes: &[i64]) -> Model3D {
+        let term: Term = sizes
+            .iter()
+            .map(|&n| struc("cube".into(), vec![number_int(n); 3]))
+            .reduce(|acc, c| struc("union".into(), vec![acc, c]))
+            .unwrap();
+        Model3D::from_term(&term).unwrap()
+    }
+
+    fn changed_leaves(diff: &ModelDiff) -> usize {
+        match diff {
+            ModelDiff::Unchanged => 0,
+            ModelDiff::Changed(children) if children.is_empty() => 1,
+            ModelDiff::Changed(children) => children.iter().map(changed_leaves).sum(),
+        }
+    }
+
+    #[test]
+    fn test_diff_model_identical() {
+        let a = union_of_cubes(&[1, 2, 3, 4]);
+        let b = union_of_cubes(&[1, 2, 3, 4]);
+        assert_eq!(a.structural_hash(&[]), b.structural_hash(&[]));
+        assert_eq!(diff_model(&a, &b, &[]), ModelDiff::Unchanged);
+    }
+
+    #[test]
+    fn test_diff_model_single_leaf_changed() {
+        let old = union_of_cubes(&[1, 2, 3, 4, 5, 6, 7, 8]);
+        let new = union_of_cubes(&[1, 2, 3, 4, 50, 6, 7, 8]);
+        assert_ne!(old.structural_hash(&[]), new.structural_hash(&[]));
+        let diff = diff_model(&old, &new, &[]);
+        assert_eq!(changed_leaves(&diff), 1);
+        // union(union(union(union(c1..c4), c5), c6), c7), c8): c5 の経路以外は Unchanged
+        let mut node = &diff;
+        for _ in 0..3 {
+            match node {
+                ModelDiff::Changed(children) => {
+                    assert_eq!(children[1], ModelDiff::Unchanged);
+                    node = &children[0];
+                }
+                ModelDiff::Unchanged => panic!("Expected Changed"),
+            }
+        }
+        assert_eq!(
+            *node,
+            ModelDiff::Changed(vec![ModelDiff::Unchanged, ModelDiff::Changed(vec![])])
+        );
+    }
+
+    #[test]
+    fn test_diff_model_kind_changed() {
+        let old = union_of_cubes(&[1, 2]);
+        let new = Model3D::Difference(
+            Box::new(Model3D::Cube {
+                x: 1.0,
+                y: 1.0,
+                z: 1.0,
+            }),
+            Box::new(Model3D::Cube {
+                x: 2.0,
+                y: 2.0,
+                z: 2.0,
+            }),
+        );
+        assert_eq!(diff_model(&old, &new, &[]), ModelDiff::Changed(vec![]));
+    }
+
+    #[test]
+    fn test_diff_model_detects_modified_stl() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = std::fs::File::create(dir.path().join("part.stl")).unwrap();
+        let include_paths = [dir.path().to_path_buf()];
+        let model = Model3D::Stl {
+            path: "part.stl".to_string(),
+        };
+        let before = model.structural_hash(&include_paths);
+        assert_eq!(model.structural_hash(&include_paths), before);
+
+        let later =
+            file.metadata().unwrap().modified().unwrap() + std::time::Duration::from_secs(10);
+        file.set_modified(later).unwrap();
+        assert_ne!(model.structural_hash(&include_paths), before);
+    }
+
+    #[test]
+    fn test_union_chain_matches_pairwise() {
+        let (t, _) = crate::parse::parse_single_term(
+            "cube(2, 2, 2) + translate(cube(2, 2, 2), 1, 0, 0) + translate(cube(2, 2, 2), 2, 0, 0) \
+             + translate(cube(2, 2, 2), 3, 0, 0) + translate(cube(2, 2, 2), 10, 0, 0)",
+        )
+        .unwrap();
+        let expr = Model3D::from_term(&t).unwrap();
+        assert_eq!(flatten_chain(&expr, Model3D::as_union).len(), 5);
+
+        let pairwise = [0.0, 1.0, 2.0, 3.0, 10.0]
+            .iter()
+            .map(|&x| Manifold::cube(2.0, 2.0, 2.0).translate(x, 0.0, 0.0))
+            .reduce(|acc, m| acc.union(&m))
+            .unwrap();
+        let batched = expr.to_mesh(&[]).unwrap();
+        let expected = mesh_volume_moment(&pairwise.to_mesh()).0;
+        assert!((mesh_volume_moment(&batched).0 - expected).abs() < 1e-3);
+        assert!((expected - 28.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_difference_chain_collects_subtrahends() {
+        let (t, _) = crate::parse::parse_single_term(
+            "cube(10, 10, 10) - cube(1, 1, 1) - translate(cube(1, 1, 1), 5, 5, 5)",
+        )
+        .unwrap();
+        let expr = Model3D::from_term(&t).unwrap();
+        let mesh = expr.to_mesh(&[]).unwrap();
+        assert!((mesh_volume_moment(&mesh).0 - 998.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_with_segments_overrides_only_subtree() {
+        let (t, _) = crate::parse::parse_single_term(
+            "with_segments(sphere(1), 8) + translate(sphere(1), 5, 0, 0)",
+        )
+        .unwrap();
+        let expr = Model3D::from_term(&t).unwrap();
+        let mesh = expr.evaluate(&[]).unwrap().to_mesh();
+        let coarse = Manifold::sphere(1.0, 8).to_mesh();
+        let fine = Manifold::sphere(1.0, DEFAULT_SEGMENTS).to_mesh();
+        assert_eq!(
+            mesh.indices().len(),
+            coarse.indices().len() + fine.indices().len()
+        );
+    }
+
+    #[test]
+    fn test_with_segments_rejects_too_few() {
+        let (t, _) = crate::parse::parse_single_term("with_segments(sphere(1), 2)").unwrap();
+        assert!(matches!(
+            Model3D::from_term(&t),
+            Err(ConversionError::InvalidValue { arg_index: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_explicit_segments_win_over_with_segments() {
+        let (t, _) = crate::parse::parse_single_term("with_segments(sphere(1, 8), 64)").unwrap();
+        let mesh = Model3D::from_term(&t)
+            .unwrap()
+            .evaluate(&[])
+            .unwrap()
+            .to_mesh();
+        let coarse = Manifold::sphere(1.0, 8).to_mesh();
+        assert_eq!(mesh.indices().len(), coarse.indices().len());
+    }
+
+    #[test]
+    fn test_primitive_segments_reject_too_few() {
+        for src in [
+            "sphere(1, 2)",
+            "cylinder(1, 2, 2)",
+            "revolve(circle(1, 2), 360)",
+        ] {
+            let (t, _) = crate::parse::parse_single_term(src).unwrap();
+            assert!(
+                matches!(
+                    Model3D::from_term(&t),
+                    Err(ConversionError::InvalidValue { .. })
+                ),
+                "{src}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_let_shape_follows_with_segments() {
+        let (t, _) = crate::parse::parse_single_term(
+            "let_shape(S, sphere(1), with_segments(S, 8) + translate(S, 5, 0, 0))",
+        )
+        .unwrap();
+        let expr = Model3D::from_term(&t).unwrap();
+        let mesh = expr.evaluate(&[]).unwrap().to_mesh();
+        let coarse = Manifold::sphere(1.0, 8).to_mesh();
+        let fine = Manifold::sphere(1.0, DEFAULT_SEGMENTS).to_mesh();
+        assert_eq!(
+            mesh.indices().len(),
+            coarse.indices().len() + fine.indices().len()
+        );
+    }
+
+    #[test]
+    fn test_sweep_extrude_follows_with_segments() {
+        let indices = |src: &str| {
+            let (t, _) = crate::parse::parse_single_term(src).unwrap();
+            let expr = Model3D::from_term(&t).unwrap();
+            expr.evaluate(&[]).unwrap().to_mesh().indices().len()
+        };
+        let sweep = "sweep_extrude(circle(1), path(p(0, 0), [line_to(p(0, 10))]))";
+        assert!(indices(&format!("with_segments({sweep}, 8)")) < indices(sweep));
+    }
+
+    #[test]
+    fn test_negative_segment_count_reports_value() {
+        let (t, _) = crate::parse::parse_single_term("with_segments(sphere(1), -4)").unwrap();
+        let Err(err) = Model3D::from_term(&t) else {
+            panic!("expected invalid segment count");
+        };
+        assert_eq!(
+            err.to_string(),
+            "Invalid value for with_segments arg 1: expected non-negative integer, got -4"
+        );
+    }
+
+    #[test]
+    fn test_fractional_segment_count_reports_value() {
+        let (t, _) = crate::parse::parse_single_term("with_segments(sphere(1), 12.5)").unwrap();
+        assert!(matches!(
+            Model3D::from_term(&t),
+            Err(ConversionError::InvalidValue { ref got, .. }) if got == "12.5"
+        ));
+    }
+
+    #[test]
+    fn test_division_by_zero_in_arg_is_non_finite() {
+        let (t, _) = crate::parse::parse_single_term("cube(10 / (5 - 5), 1, 1)").unwrap();
+        let Err(err) = Model3D::from_term(&t) else {
+            panic!("expected division by zero error");
+        };
+        assert!(matches!(
+            err,
+            ConversionError::NonFiniteValue { arg_index: 0, .. }
+        ));
+        assert_eq!(
+            err.to_string(),
+            "Non-finite value for cube arg 0 (division by zero?)"
+        );
+    }
+
+    #[test]
+    fn test_warp_rejects_non_finite_vertex() {
+        let (t, _) = crate::parse::parse_single_term("warp(cube(1, 1, 1), x, y / 0, z)").unwrap();
+        let model = Model3D::from_term(&t).unwrap();
+        assert!(matches!(
+            model.evaluate(&[]),
+            Err(ConversionError::NonFiniteValue { arg_index: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn test_position_bounds() {
+        let positions = [1.0, -2.0, 3.0, 5.0, 4.0, -1.0];
+        assert_eq!(
+            position_bounds(&positions),
+            Some(([1.0, -2.0, -1.0], [5.0, 4.0, 3.0]))
+        );
+        assert_eq!(position_bounds(&[]), None);
+    }
+
+    #[test]
+    fn test_center_moves_bbox_center_to_origin() {
+        let (t, _) =
+            crate::parse::parse_single_term("center(translate(cube(4, 2, 6), 10, -3, 1))").unwrap();
+        let model = Model3D::from_term(&t).unwrap();
+        assert!(matches!(model, Model3D::Center { .. }));
+        let mesh = model.evaluate(&[]).unwrap().to_mesh();
+        let (min, max) = mesh_bounds(&mesh).unwrap();
+        assert_eq!(min, [-2.0, -1.0, -3.0]);
+        assert_eq!(max, [2.0, 1.0, 3.0]);
+    }
 }
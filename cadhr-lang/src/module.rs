@@ -3,7 +3,7 @@ use std::fmt;
 use std::path::{Path, PathBuf};
 
 use crate::parse::{Clause, FileRegistry, Term, database};
-use crate::term_processor::is_builtin_functor;
+use crate::term_processor::{is_builtin_functor, is_builtin_predicate, is_raw_builtin_arg};
 
 #[derive(Debug)]
 pub enum ModuleError {
@@ -192,14 +192,25 @@ fn prefix_term(term: &Term, module_name: &str) -> Term {
             args,
             span,
         } => {
-            let prefixed_functor = if is_builtin_functor(functor) {
+            let prefixed_functor = if is_builtin_functor(functor) || is_builtin_predicate(functor) {
                 functor.clone()
             } else {
                 format!("{}::{}", module_name, functor)
             };
+            let args = args
+                .iter()
+                .enumerate()
+                .map(|(i, a)| {
+                    if is_raw_builtin_arg(functor, args.len(), i) {
+                        a.clone()
+                    } else {
+                        prefix_term(a, module_name)
+                    }
+                })
+                .collect();
             Term::Struct {
                 functor: prefixed_functor,
-                args: args.iter().map(|a| prefix_term(a, module_name)).collect(),
+                args,
                 span: *span,
             }
         }
@@ -378,6 +389,68 @@ mod tests {
         assert!(functors.contains(&"parts::bolt".to_string()));
     }
 
+    #[test]
+    fn test_builtin_predicate_not_prefixed() {
+        let dir = tempfile::tempdir().unwrap();
+        let full = dir.path().join("parts/db.cadhr");
+        fs::create_dir_all(full.parent().unwrap()).unwrap();
+        fs::write(&full, "uniq(S) :- list_to_set([1, 2, 1], S).\n").unwrap();
+
+        let clauses = vec![Clause::Use {
+            path: "parts".to_string(),
+            expose: vec![],
+            span: None,
+        }];
+        let result = resolve_modules(
+            clauses,
+            &[dir.path().to_path_buf()],
+            &mut HashSet::new(),
+            &mut FileRegistry::new(),
+        )
+        .unwrap();
+        let body_functors: Vec<&str> = result
+            .iter()
+            .flat_map(|c| match c {
+                Clause::Rule { body, .. } => body.iter().collect(),
+                _ => vec![],
+            })
+            .filter_map(|t| match t {
+                Term::Struct { functor, .. } => Some(functor.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(body_functors, vec!["list_to_set"]);
+    }
+
+    #[test]
+    fn test_same_predicate_in_two_modules() {
+        use crate::parse::query;
+        use crate::term_rewrite::execute;
+
+        let dir = tempfile::tempdir().unwrap();
+        for (path, contents) in [
+            ("fasteners/db.cadhr", "part(X) :- cylinder(X, 10).\n"),
+            ("brackets/db.cadhr", "part(X) :- cube(X, X, 2).\n"),
+        ] {
+            let full = dir.path().join(path);
+            fs::create_dir_all(full.parent().unwrap()).unwrap();
+            fs::write(&full, contents).unwrap();
+        }
+
+        let clauses = database("#use(\"fasteners\").\n#use(\"brackets\").\n").unwrap();
+        let mut db = resolve_modules(
+            clauses,
+            &[dir.path().to_path_buf()],
+            &mut HashSet::new(),
+            &mut FileRegistry::new(),
+        )
+        .unwrap();
+        let (_, q) = query("brackets::part(4), fasteners::part(3).").unwrap();
+        let (resolved, _) = execute(&mut db, q).unwrap();
+        let resolved: Vec<String> = resolved.iter().map(|t| format!("{:?}", t)).collect();
+        assert_eq!(resolved, vec!["cube(4, 4, 2)", "cylinder(3, 10)"]);
+    }
+
     #[test]
     fn test_non_use_clauses_preserved() {
         let clauses = vec![Clause::Fact(Term::Struct {
@@ -0,0 +1,205 @@
+//! パラメータ探索: クエリ変数を複数の値に束縛して実行し、値ごとのメッシュを得る
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::PathBuf;
+
+use manifold_rs::Mesh;
+
+use crate::manifold_bridge::{ConversionError, generate_mesh_and_tree_from_terms};
+use crate::module::resolve_modules;
+use crate::parse::{
+    Bound, FileRegistry, FixedPoint, SrcSpan, Term, database, query, substitute_query_params,
+};
+use crate::term_rewrite::{CadhrError, RewriteError, collect_named_vars, execute};
+
+#[derive(Debug, Clone)]
+pub enum SweepError {
+    Parse(String),
+    Module(String),
+    Rewrite(RewriteError),
+    Mesh(ConversionError),
+}
+
+impl fmt::Display for SweepError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SweepError::Parse(message) => write!(f, "Parse error: {}", message),
+            SweepError::Module(message) => write!(f, "Module error: {}", message),
+            SweepError::Rewrite(e) => write!(f, "Rewrite error: {}", e),
+            SweepError::Mesh(e) => write!(f, "Mesh error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SweepError {}
+
+impl CadhrError for SweepError {
+    fn error_message(&self) -> String {
+        self.to_string()
+    }
+    fn span(&self) -> Option<SrcSpan> {
+        match self {
+            SweepError::Rewrite(e) => e.span(),
+            SweepError::Mesh(e) => e.span(),
+            SweepError::Parse(_) | SweepError::Module(_) => None,
+        }
+    }
+}
+
+/// クエリ変数 `var` を `values` の各値に置き換えて実行し、値ごとのメッシュを返す。
+/// パースやモジュール解決に失敗した場合は全要素が同じエラーになる。
+pub fn sweep(
+    db_src: &str,
+    query_src: &str,
+    var: &str,
+    values: &[FixedPoint],
+    include_paths: &[PathBuf],
+) -> Vec<Result<Mesh, SweepError>> {
+    let parsed = (|| -> Result<_, SweepError> {
+        let db = database(db_src).map_err(|e| SweepError::Parse(format!("{:?}", e)))?;
+        let (_, query_terms) =
+            query(query_src).map_err(|e| SweepError::Parse(format!("{:?}", e)))?;
+        let db = resolve_modules(
+            db,
+            include_paths,
+            &mut HashSet::new(),
+            &mut FileRegistry::new(),
+        )
+        .map_err(|e| SweepError::Module(e.to_string()))?;
+        Ok((db, query_terms))
+    })();
+    let (db, query_terms) = match parsed {
+        Ok(pair) => pair,
+        Err(e) => return values.iter().map(|_| Err(e.clone())).collect(),
+    };
+
+    values
+        .iter()
+        .map(|value| {
+            let bindings = HashMap::from([(var.to_string(), value.to_f64())]);
+            let substituted = substitute_query_params(&query_terms, &bindings);
+            let mut db = db.clone();
+            let (resolved, _) = execute(&mut db, substituted).map_err(SweepError::Rewrite)?;
+            let (mesh, _) = generate_mesh_and_tree_from_terms(&resolved, include_paths)
+                .map_err(SweepError::Mesh)?;
+            Ok(mesh)
+        })
+        .collect()
+}
+
+/// 乱択探索用の SplitMix64。seed が同じなら同じ列になる
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+/// 下限・上限の両方を持つ範囲変数を、範囲内の値（0.01 刻み）を既定値として束縛する。
+/// 同名の変数は同じ値になり、seed が同じなら結果も同じ
+pub fn sample_ranges(terms: &[Term], seed: u64) -> Vec<Term> {
+    let mut ranges: Vec<(String, Bound, Bound)> = Vec::new();
+    for term in terms {
+        collect_named_vars(term, &mut |v| {
+            if let Term::Var {
+                name,
+                min: Some(min),
+                max: Some(max),
+                ..
+            } = v
+                && !ranges.iter().any(|(n, ..)| n == name)
+            {
+                ranges.push((name.clone(), *min, *max));
+            }
+        });
+    }
+
+    let mut rng = SplitMix64(seed);
+    let values = ranges
+        .into_iter()
+        .filter_map(|(name, min, max)| {
+            let lo = min.value.raw() + i64::from(!min.inclusive);
+            let hi = max.value.raw() - i64::from(!max.inclusive);
+            (lo <= hi).then(|| {
+                let span = (hi - lo) as u64 + 1;
+                let sampled = lo + (rng.next_u64() % span) as i64;
+                (name, FixedPoint::from_hundredths(sampled).to_f64())
+            })
+        })
+        .collect();
+    substitute_query_params(terms, &values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn x_extent(mesh: &Mesh) -> (f32, f32) {
+        let stride = mesh.num_props() as usize;
+        mesh.vertices()
+            .chunks(stride)
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), v| {
+                (lo.min(v[0]), hi.max(v[0]))
+            })
+    }
+
+    #[test]
+    fn test_sweep_cube_width() {
+        let values = [10, 20, 30].map(FixedPoint::from_int);
+        let meshes = sweep("box(W) :- cube(W, 10, 10).", "box(W).", "W", &values, &[]);
+        assert_eq!(meshes.len(), 3);
+        let extents: Vec<(f32, f32)> = meshes
+            .iter()
+            .map(|m| x_extent(m.as_ref().unwrap()))
+            .collect();
+        assert_eq!(extents, vec![(0.0, 10.0), (0.0, 20.0), (0.0, 30.0)]);
+    }
+
+    fn sampled_defaults(query_src: &str, seed: u64) -> Vec<FixedPoint> {
+        let (_, terms) = query(query_src).unwrap();
+        let mut defaults = Vec::new();
+        for term in sample_ranges(&terms, seed) {
+            collect_named_vars(&term, &mut |v| {
+                if let Term::Var {
+                    default_value: Some(value),
+                    ..
+                } = v
+                {
+                    defaults.push(*value);
+                }
+            });
+        }
+        defaults
+    }
+
+    #[test]
+    fn test_sample_ranges_is_deterministic_per_seed() {
+        let src = "box(0<W<100, 0<=D<=100, W, H).";
+        let a = sampled_defaults(src, 42);
+        assert_eq!(a.len(), 3);
+        assert_eq!(a[0], a[2]);
+        for value in &a {
+            assert!(value.raw() > 0 && value.raw() <= 10000);
+        }
+        assert_eq!(a, sampled_defaults(src, 42));
+        assert_ne!(a, sampled_defaults(src, 7));
+    }
+
+    #[test]
+    fn test_sweep_parse_error_for_every_value() {
+        let values = [1, 2].map(FixedPoint::from_int);
+        let results = sweep("box(W) :- cube(W", "box(W).", "W", &values, &[]);
+        assert_eq!(results.len(), 2);
+        assert!(
+            results
+                .iter()
+                .all(|r| matches!(r, Err(SweepError::Parse(_))))
+        );
+    }
+}
@@ -3,11 +3,12 @@ use nom::{
     branch::alt,
     bytes::complete::{is_not, tag, take_until, take_while, take_while1},
     character::complete::{char, digit1, multispace1},
-    combinator::{cut, map, map_res, opt, recognize, value},
+    combinator::{cut, map, map_res, not, opt, recognize, value},
     multi::{many0, separated_list0, separated_list1},
     sequence::{delimited, pair, preceded, separated_pair, terminated},
 };
 use std::fmt;
+use std::hash::{Hash, Hasher};
 
 // ============================================================
 // SrcSpan: ソースコード上のバイトオフセット範囲
@@ -97,12 +98,39 @@ impl FixedPoint {
     pub fn to_f64(self) -> f64 {
         self.0 as f64 / 100.0
     }
+    pub fn is_integral(self) -> bool {
+        self.0 % 100 == 0
+    }
+    /// 0 除算なら None
+    pub fn checked_div(self, rhs: Self) -> Option<Self> {
+        (rhs.0 != 0).then(|| self / rhs)
+    }
     pub fn to_i64_checked(self) -> Option<i64> {
-        (self.0 % 100 == 0).then(|| self.0 / 100)
+        self.is_integral().then(|| self.0 / 100)
     }
     pub fn raw(self) -> i64 {
         self.0
     }
+    /// 小数点以下を常に digits 桁で表示する。2 桁未満では四捨五入（0 から遠い方へ）
+    pub fn format_with_precision(self, digits: usize) -> String {
+        let shown = digits.min(2);
+        let unit = 10u64.pow((2 - shown) as u32);
+        let rounded = (self.0.unsigned_abs() + unit / 2) / unit;
+        let scale = 10u64.pow(shown as u32);
+        let (whole, frac) = (rounded / scale, rounded % scale);
+        let sign = if self.0 < 0 && rounded != 0 { "-" } else { "" };
+        if digits == 0 {
+            format!("{}{}", sign, whole)
+        } else {
+            format!(
+                "{}{}.{:0shown$}{}",
+                sign,
+                whole,
+                frac,
+                "0".repeat(digits - shown)
+            )
+        }
+    }
 }
 
 impl fmt::Debug for FixedPoint {
@@ -113,7 +141,7 @@ impl fmt::Debug for FixedPoint {
 
 impl fmt::Display for FixedPoint {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        if self.0 % 100 == 0 {
+        if self.is_integral() {
             write!(f, "{}", self.0 / 100)
         } else {
             let abs = self.0.unsigned_abs();
@@ -129,6 +157,17 @@ impl fmt::Display for FixedPoint {
     }
 }
 
+/// 数値リテラルと同じ書式 (`-12`, `3.5`, `0.25`) のみ受け付ける
+impl std::str::FromStr for FixedPoint {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, String> {
+        match fixed_number(s) {
+            Ok(("", value)) => Ok(value),
+            _ => Err(format!("not a number: {:?}", s)),
+        }
+    }
+}
+
 impl std::ops::Add for FixedPoint {
     type Output = Self;
     fn add(self, rhs: Self) -> Self {
@@ -182,13 +221,13 @@ impl From<i64> for FixedPoint {
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub struct Bound {
     pub value: FixedPoint,
     pub inclusive: bool,
 }
 
-#[derive(Clone, Copy, PartialEq, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub enum ArithOp {
     Add,
     Sub,
@@ -354,6 +393,50 @@ impl<Scope: PartialEq> PartialEq for Term<Scope> {
     }
 }
 
+impl<Scope: Eq> Eq for Term<Scope> {}
+
+/// PartialEq と同じく span は無視する
+impl<Scope: Hash> Hash for Term<Scope> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Term::Var {
+                name,
+                scope,
+                default_value,
+                min,
+                max,
+                ..
+            } => {
+                name.hash(state);
+                scope.hash(state);
+                default_value.hash(state);
+                min.hash(state);
+                max.hash(state);
+            }
+            Term::Number { value } => value.hash(state),
+            Term::InfixExpr { op, left, right } => {
+                op.hash(state);
+                left.hash(state);
+                right.hash(state);
+            }
+            Term::Struct { functor, args, .. } => {
+                functor.hash(state);
+                args.hash(state);
+            }
+            Term::List { items, tail } => {
+                items.hash(state);
+                tail.hash(state);
+            }
+            Term::StringLit { value } => value.hash(state),
+            Term::Constraint { left, right } => {
+                left.hash(state);
+                right.hash(state);
+            }
+        }
+    }
+}
+
 #[derive(Clone, PartialEq)]
 pub enum Clause<Scope = ()> {
     Fact(Term<Scope>),
@@ -370,8 +453,48 @@ pub enum Clause<Scope = ()> {
 
 pub type ScopedClause = Clause<ScopeId>;
 
+/// エラーメッセージに載せる項の深さの上限
+pub const ERROR_TERM_DEPTH: usize = 16;
+
 impl<Scope> fmt::Debug for Term<Scope> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_with_depth(f, None)
+    }
+}
+
+/// Term::elided の表示
+pub struct Elided<'a, Scope> {
+    term: &'a Term<Scope>,
+    max_depth: usize,
+}
+
+impl<Scope> fmt::Display for Elided<'_, Scope> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.term.fmt_with_depth(f, Some(self.max_depth))
+    }
+}
+
+impl<Scope> Term<Scope> {
+    /// Debug と同じ表記だが、max_depth より深い複合項は `...` に省略する
+    pub fn elided(&self, max_depth: usize) -> Elided<'_, Scope> {
+        Elided {
+            term: self,
+            max_depth,
+        }
+    }
+
+    /// depth が None なら省略しない
+    fn fmt_with_depth(&self, f: &mut fmt::Formatter<'_>, depth: Option<usize>) -> fmt::Result {
+        let is_compound = match self {
+            Term::Struct { args, .. } => !args.is_empty(),
+            Term::List { items, tail } => !items.is_empty() || tail.is_some(),
+            Term::InfixExpr { .. } | Term::Constraint { .. } => true,
+            Term::Var { .. } | Term::Number { .. } | Term::StringLit { .. } => false,
+        };
+        if depth == Some(0) && is_compound {
+            return write!(f, "...");
+        }
+        let child = depth.map(|d| d.saturating_sub(1));
         match self {
             Term::Var {
                 name,
@@ -400,7 +523,11 @@ impl<Scope> fmt::Debug for Term<Scope> {
                     ArithOp::Mul => "*",
                     ArithOp::Div => "/",
                 };
-                write!(f, "({:?} {} {:?})", left, op_str, right)
+                write!(f, "(")?;
+                left.fmt_with_depth(f, child)?;
+                write!(f, " {} ", op_str)?;
+                right.fmt_with_depth(f, child)?;
+                write!(f, ")")
             }
             Term::Struct { functor, args, .. } => {
                 write!(f, "{}", functor)?;
@@ -410,7 +537,7 @@ impl<Scope> fmt::Debug for Term<Scope> {
                         if idx > 0 {
                             write!(f, ", ")?;
                         }
-                        write!(f, "{:?}", arg)?;
+                        arg.fmt_with_depth(f, child)?;
                     }
                     write!(f, ")")?;
                 }
@@ -422,19 +549,23 @@ impl<Scope> fmt::Debug for Term<Scope> {
                     if idx > 0 {
                         write!(f, ", ")?;
                     }
-                    write!(f, "{:?}", item)?;
+                    item.fmt_with_depth(f, child)?;
                 }
                 if let Some(tail) = tail {
                     if !items.is_empty() {
                         write!(f, " | ")?;
                     }
-                    write!(f, "{:?}", tail)?;
+                    tail.fmt_with_depth(f, child)?;
                 }
                 write!(f, "]")
             }
             Term::StringLit { value } => write!(f, "\"{}\"", value),
             Term::Constraint { left, right } => {
-                write!(f, "constraint({:?} = {:?})", left, right)
+                write!(f, "constraint(")?;
+                left.fmt_with_depth(f, child)?;
+                write!(f, " = ")?;
+                right.fmt_with_depth(f, child)?;
+                write!(f, ")")
             }
         }
     }
@@ -686,10 +817,50 @@ fn variable(input: &str) -> PResult<'_, String> {
     .parse(input)
 }
 
+/// `_` 区切りを許す数字列 (`10_000`)。先頭・末尾・連続した `_` は含まない
+fn digits(input: &str) -> PResult<'_, &str> {
+    recognize(pair(digit1, many0(preceded(char('_'), digit1)))).parse(input)
+}
+
+fn hex_digits(input: &str) -> PResult<'_, &str> {
+    let hex = || take_while1(|c: char| c.is_ascii_hexdigit());
+    recognize(pair(hex(), many0(preceded(char('_'), hex())))).parse(input)
+}
+
+fn bin_digits(input: &str) -> PResult<'_, &str> {
+    let bin = || take_while1(|c: char| c == '0' || c == '1');
+    recognize(pair(bin(), many0(preceded(char('_'), bin())))).parse(input)
+}
+
+/// 0x / 0b 接頭辞付きの整数。小数部は書けない
+fn radix_integer(input: &str) -> PResult<'_, FixedPoint> {
+    terminated(
+        map_res(
+            (
+                opt(char('-')),
+                alt((
+                    pair(value(16, tag("0x")), hex_digits),
+                    pair(value(2, tag("0b")), bin_digits),
+                )),
+            ),
+            |(neg, (radix, body))| -> Result<FixedPoint, std::num::ParseIntError> {
+                let v = i64::from_str_radix(&body.replace('_', ""), radix)?;
+                Ok(FixedPoint::from_int(if neg.is_some() { -v } else { v }))
+            },
+        ),
+        cut(not(pair(char('.'), digit1))),
+    )
+    .parse(input)
+}
+
 fn fixed_number(input: &str) -> PResult<'_, FixedPoint> {
+    if let Ok(result) = radix_integer(input) {
+        return Ok(result);
+    }
     map_res(
-        recognize((opt(char('-')), digit1, opt(pair(char('.'), digit1)))),
+        recognize((opt(char('-')), digits, opt(pair(char('.'), digits)))),
         |s: &str| -> Result<FixedPoint, String> {
+            let s = &s.replace('_', "");
             if let Some(dot_pos) = s.find('.') {
                 let int_part: i64 = s[..dot_pos]
                     .parse()
@@ -959,19 +1130,27 @@ fn simple_term(input: &str) -> PResult<'_, Term> {
     add_expr(input)
 }
 
-/// Pipe operator: `a |> f(b, c)` becomes `f(a, b, c)`
-fn pipe_expr(input: &str) -> PResult<'_, Term> {
-    let (input, first) = simple_term(input)?;
-    let (input, rest) = many0(preceded(ws(tag("|>")), simple_term)).parse(input)?;
+fn is_pipe_placeholder<S>(term: &Term<S>) -> bool {
+    matches!(term, Term::Var { name, .. } if name == "_")
+}
 
-    let result = rest.into_iter().fold(first, |acc, rhs| match rhs {
+/// `acc |> rhs` の書き換え。rhs の引数にちょうど1つ `_` があればそこへ、なければ先頭に acc を入れる。
+pub fn pipe_into<S>(acc: Term<S>, rhs: Term<S>) -> Term<S> {
+    match rhs {
         Term::Struct {
             functor,
-            args,
+            mut args,
             span,
         } => {
-            let mut new_args = vec![acc];
-            new_args.extend(args);
+            let new_args = if args.iter().filter(|a| is_pipe_placeholder(a)).count() == 1 {
+                let pos = args.iter().position(is_pipe_placeholder).unwrap();
+                args[pos] = acc;
+                args
+            } else {
+                let mut new_args = vec![acc];
+                new_args.extend(args);
+                new_args
+            };
             Term::Struct {
                 functor,
                 args: new_args,
@@ -979,8 +1158,16 @@ fn pipe_expr(input: &str) -> PResult<'_, Term> {
             }
         }
         other => struc("apply".to_string(), vec![other, acc]),
-    });
-    Ok((input, result))
+    }
+}
+
+/// Pipe operator: `a |> f(b, c)` becomes `f(a, b, c)`, and a bare atom `a |> f` becomes `f(a)`.
+/// If the rhs has exactly one `_` argument, `a` replaces it instead: `a |> f(b, _)` becomes `f(b, a)`.
+/// Only a non-callable rhs (number, list, arithmetic expression...) falls back to `apply(rhs, a)`.
+fn pipe_expr(input: &str) -> PResult<'_, Term> {
+    let (input, first) = simple_term(input)?;
+    let (input, rest) = many0(preceded(ws(tag("|>")), simple_term)).parse(input)?;
+    Ok((input, rest.into_iter().fold(first, pipe_into)))
 }
 
 pub(super) fn term(input: &str) -> PResult<'_, Term> {
@@ -1189,6 +1376,44 @@ pub fn database(input: &str) -> Result<Vec<Clause>, nom::Err<nom::error::Error<&
     }
 }
 
+/// database と同じだが最初のエラーで止まらず、壊れた節を読み飛ばして全エラーを集める（エディタ表示用）。
+/// 失敗した節は次の節末尾の `.`（直後が空白か入力末尾）まで飛ばして再開する
+pub fn database_collect_errors(
+    input: &str,
+) -> (Vec<Clause>, Vec<nom::Err<nom::error::Error<&str>>>) {
+    let base = input.as_ptr() as usize;
+    let mut clauses = Vec::new();
+    let mut errors = Vec::new();
+    let mut rest = input;
+    loop {
+        rest = match space_or_comment0(rest) {
+            Ok((r, _)) => r,
+            Err(_) => rest,
+        };
+        if rest.is_empty() {
+            break;
+        }
+        match clause_parser(rest) {
+            Ok((r, mut clause)) => {
+                fix_spans_in_clause(&mut clause, base);
+                clauses.push(clause);
+                rest = r;
+            }
+            Err(e) => {
+                errors.push(e);
+                let end = rest
+                    .char_indices()
+                    .find(|&(i, c)| {
+                        c == '.' && rest[i + 1..].chars().next().is_none_or(char::is_whitespace)
+                    })
+                    .map_or(rest.len(), |(i, _)| i + 1);
+                rest = &rest[end..];
+            }
+        }
+    }
+    (clauses, errors)
+}
+
 pub fn query(input: &str) -> PResult<'_, Vec<Term>> {
     let base = input.as_ptr() as usize;
     let (rest, mut terms) = ws(terminated(goals, cut(ws(char('.'))))).parse(input)?;
@@ -1198,6 +1423,14 @@ pub fn query(input: &str) -> PResult<'_, Vec<Term>> {
     Ok((rest, terms))
 }
 
+/// REPL入力用: 項を1つだけパースし、残りの入力と共に返す（末尾の `.` は不要）
+pub fn parse_single_term(input: &str) -> Result<(Term, &str), nom::Err<nom::error::Error<&str>>> {
+    let base = input.as_ptr() as usize;
+    let (rest, mut t) = ws(term).parse(input)?;
+    fix_spans_in_term(&mut t, base);
+    Ok((t, rest))
+}
+
 /// query変数のパラメータ情報（UIスライダー用）
 #[derive(Clone, Debug)]
 pub struct QueryParam {
@@ -1537,6 +1770,61 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parse_fixed_point_with_underscores() {
+        let (_, clause) = clause_parser("f(10_000).").unwrap();
+        match clause {
+            Clause::Fact(Term::Struct { args, .. }) => match &args[0] {
+                Term::Number { value } => assert_eq!(*value, FixedPoint::from_int(10000)),
+                _ => panic!("Expected Number"),
+            },
+            _ => panic!("Expected Fact"),
+        }
+        assert_eq!("1_0.5_0".parse(), Ok(FixedPoint::from_hundredths(1050)));
+        assert_eq!("-1_000.5".parse(), Ok(FixedPoint::from_hundredths(-100050)));
+    }
+
+    #[test]
+    fn parse_fixed_point_rejects_misplaced_underscores() {
+        for src in ["_10", "10_", "1__0", "10._5", "10_.5"] {
+            assert!(src.parse::<FixedPoint>().is_err(), "{src}");
+        }
+        assert!(clause_parser("f(10_).").is_err());
+    }
+
+    #[test]
+    fn database_collect_errors_skips_bad_clauses() {
+        let src = "a(1).\nb(2.\nc(3).\nd(, x).\n";
+        let (clauses, errors) = database_collect_errors(src);
+        let shown: Vec<String> = clauses.iter().map(|c| format!("{:?}", c)).collect();
+        assert_eq!(shown, vec!["a(1).", "c(3)."]);
+        assert_eq!(errors.len(), 2);
+        let lines: Vec<usize> = errors
+            .iter()
+            .map(|e| {
+                let span = parse_error_span(src, e).unwrap();
+                src[..span.start].matches('\n').count() + 1
+            })
+            .collect();
+        assert_eq!(lines, vec![2, 4]);
+
+        let (clauses, errors) = database_collect_errors("p(1).\nq(1.5).");
+        assert_eq!(clauses.len(), 2);
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn parse_radix_integers() {
+        assert_eq!("0xFF".parse(), Ok(FixedPoint::from_int(255)));
+        assert_eq!("0b1010".parse(), Ok(FixedPoint::from_int(10)));
+        assert_eq!("-0x1_0".parse(), Ok(FixedPoint::from_int(-16)));
+        for src in ["0x1.5", "0b102", "0x", "0b1.0"] {
+            assert!(src.parse::<FixedPoint>().is_err(), "{src}");
+        }
+        let (_, clause) = clause_parser("pins(0x10, 0b11).").unwrap();
+        assert_eq!(format!("{:?}", clause), "pins(16, 3).");
+    }
+
     #[test]
     fn parse_fixed_point_display() {
         assert_eq!(format!("{}", FixedPoint::from_int(100)), "100");
@@ -1545,6 +1833,52 @@ mod tests {
         assert_eq!(format!("{}", FixedPoint::from_hundredths(-350)), "-3.5");
     }
 
+    #[test]
+    fn elided_debug_caps_depth() {
+        let mut term = a("leaf");
+        for _ in 0..5000 {
+            term = struc("f".to_string(), vec![term]);
+        }
+        let shown = format!("{}", term.elided(3));
+        assert_eq!(shown, "f(f(f(...)))");
+
+        let shallow: Term = struc(
+            "g".to_string(),
+            vec![a("x"), list(vec![number_int(1)], None)],
+        );
+        assert_eq!(format!("{}", shallow.elided(2)), format!("{:?}", shallow));
+        assert_eq!(format!("{}", shallow.elided(1)), "g(x, ...)");
+    }
+
+    #[test]
+    fn fixed_point_format_with_precision() {
+        let v = FixedPoint::from_hundredths(350);
+        assert_eq!(v.format_with_precision(0), "4");
+        assert_eq!(v.format_with_precision(1), "3.5");
+        assert_eq!(v.format_with_precision(2), "3.50");
+        assert_eq!(v.format_with_precision(3), "3.500");
+        assert_eq!(
+            FixedPoint::from_hundredths(-349).format_with_precision(1),
+            "-3.5"
+        );
+        assert_eq!(
+            FixedPoint::from_hundredths(-4).format_with_precision(1),
+            "0.0"
+        );
+        assert_eq!(FixedPoint::from_int(7).format_with_precision(2), "7.00");
+    }
+
+    #[test]
+    fn fixed_point_is_integral() {
+        assert!(FixedPoint::from_int(5).is_integral());
+        assert!(FixedPoint::from_hundredths(500).is_integral());
+        assert!(FixedPoint::from_int(-3).is_integral());
+        assert!(!FixedPoint::from_hundredths(350).is_integral());
+        assert!(!FixedPoint::from_hundredths(-1).is_integral());
+        assert_eq!(FixedPoint::from_hundredths(350).to_i64_checked(), None);
+        assert_eq!(FixedPoint::from_hundredths(-500).to_i64_checked(), Some(-5));
+    }
+
     #[test]
     fn parse_default_var_decimal() {
         let src = "hoge(X@2.5).";
@@ -1565,6 +1899,39 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parse_default_var_with_range() {
+        // 頭部では範囲を書けないので、クエリとして読む
+        let (_, terms) = query("hoge(5<X@10<=20).").unwrap();
+        let Term::Struct { args, .. } = &terms[0] else {
+            panic!("Expected Struct");
+        };
+        let Term::Var {
+            default_value,
+            min,
+            max,
+            ..
+        } = &args[0]
+        else {
+            panic!("Expected Var");
+        };
+        assert_eq!(*default_value, Some(FixedPoint::from_int(10)));
+        assert_eq!(
+            *min,
+            Some(Bound {
+                value: FixedPoint::from_int(5),
+                inclusive: false
+            })
+        );
+        assert_eq!(
+            *max,
+            Some(Bound {
+                value: FixedPoint::from_int(20),
+                inclusive: true
+            })
+        );
+    }
+
     #[test]
     fn parse_pipe_operator_chain() {
         // a |> b |> c should become c(b(a))
@@ -1662,6 +2029,36 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parse_pipe_into_bare_atom() {
+        let (t, _) = parse_single_term("5 |> inc").unwrap();
+        assert_eq!(format!("{:?}", t), "inc(5)");
+        let (t, _) = parse_single_term("cube(1,1,1) |> twice |> shrink").unwrap();
+        assert_eq!(format!("{:?}", t), "shrink(twice(cube(1, 1, 1)))");
+    }
+
+    #[test]
+    fn parse_pipe_placeholder() {
+        let (t, _) = parse_single_term("cube(1,1,1) |> translate(10, _, 0)").unwrap();
+        assert_eq!(format!("{:?}", t), "translate(10, cube(1, 1, 1), 0)");
+        let (t, _) = parse_single_term("a |> f(_) |> g(1, _)").unwrap();
+        assert_eq!(format!("{:?}", t), "g(1, f(a))");
+    }
+
+    #[test]
+    fn parse_pipe_prepends_without_single_placeholder() {
+        let (t, _) = parse_single_term("cube(1,1,1) |> translate(10, 0, 0)").unwrap();
+        assert_eq!(format!("{:?}", t), "translate(cube(1, 1, 1), 10, 0, 0)");
+        let (t, _) = parse_single_term("a |> f(_, _)").unwrap();
+        assert_eq!(format!("{:?}", t), "f(a, _, _)");
+    }
+
+    #[test]
+    fn parse_pipe_into_non_callable_uses_apply() {
+        let (t, _) = parse_single_term("cube(1,1,1) |> 5").unwrap();
+        assert_eq!(format!("{:?}", t), "apply(5, cube(1, 1, 1))");
+    }
+
     #[test]
     fn parse_annotated_var_with_default_and_range_in_body() {
         let src = "hoge(X@20) :- 0<X<50, cube(X).";
@@ -1815,4 +2212,30 @@ mod tests {
         assert_eq!(db.len(), 2);
         assert!(matches!(&db[0], Clause::Use { path, .. } if path == "bolts"));
     }
+
+    #[test]
+    fn parse_single_term_without_trailing_input() {
+        let (t, rest) = parse_single_term("cube(1,1,1)").unwrap();
+        assert_eq!(format!("{:?}", t), "cube(1, 1, 1)");
+        assert_eq!(rest, "");
+        assert_eq!(first_span(&t).map(|s| (s.start, s.end)), Some((0, 11)));
+    }
+
+    #[test]
+    fn parse_single_term_with_trailing_input() {
+        let (t, rest) = parse_single_term(" cube(1,1,1) , sphere(2).").unwrap();
+        assert_eq!(format!("{:?}", t), "cube(1, 1, 1)");
+        assert_eq!(rest, ", sphere(2).");
+        assert!(parse_single_term(")").is_err());
+    }
+
+    #[test]
+    fn structurally_equal_terms_dedup_in_hash_set() {
+        let (a, _) = parse_single_term("f(X, [1, 2.5], \"s\") + g(0 < Y <= 3)").unwrap();
+        let (b, _) = parse_single_term("  f(X, [1, 2.5], \"s\") + g(0 < Y <= 3)").unwrap();
+        let (c, _) = parse_single_term("f(X, [1, 2.5], \"s\") + g(0 < Y < 3)").unwrap();
+        assert_ne!(first_span(&a), first_span(&b));
+        let set: std::collections::HashSet<Term> = [a, b, c].into_iter().collect();
+        assert_eq!(set.len(), 2);
+    }
 }
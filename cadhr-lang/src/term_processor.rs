@@ -1,4 +1,6 @@
-use crate::parse::Term;
+use crate::parse::{ScopedTerm, Term};
+use std::path::PathBuf;
+use std::time::Instant;
 
 pub struct BuiltinFunctorSet {
     pub functors: &'static [(&'static str, &'static [usize])],
@@ -8,6 +10,36 @@ pub struct BuiltinFunctorSet {
 }
 inventory::collect!(BuiltinFunctorSet);
 
+/// 書き換えずにそのまま残す組み込みファンクタの引数（warp の座標式など）。変数の束縛だけが反映される。
+pub struct BuiltinRawArgs {
+    pub functor: &'static str,
+    pub arity: usize,
+    pub indices: &'static [usize],
+}
+inventory::collect!(BuiltinRawArgs);
+
+/// 組み込み述語の評価結果。(引数index, 値) の組で、term_rewrite が各引数と値を単一化する。
+pub type BuiltinPredicateResult = Result<Vec<(usize, ScopedTerm)>, String>;
+
+/// 組み込み述語の評価に渡す実行時の設定
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PredicateContext<'a> {
+    /// 形状を評価する述語が stl などの相対パスを探すディレクトリ
+    pub include_paths: &'a [PathBuf],
+    /// 実行の期限。時間のかかる述語はこれを過ぎたら打ち切る
+    pub deadline: Option<Instant>,
+}
+
+/// 書き換え時に評価される組み込み述語。項としては結果に残らない。
+pub struct BuiltinPredicate {
+    pub name: &'static str,
+    pub arity: usize,
+    /// true の場合、引数内のユーザ定義項を書き換えてから eval に渡す（形状を受け取る述語向け）。
+    pub resolve_args: bool,
+    pub eval: fn(&[ScopedTerm], &PredicateContext) -> BuiltinPredicateResult,
+}
+inventory::collect!(BuiltinPredicate);
+
 pub trait TermProcessor<S = ()> {
     type Output;
     type Error;
@@ -32,8 +64,21 @@ pub fn should_resolve_args(name: &str) -> bool {
         .all(|set| set.resolve_args)
 }
 
+pub fn is_raw_builtin_arg(functor: &str, arity: usize, index: usize) -> bool {
+    inventory::iter::<BuiltinRawArgs>()
+        .any(|r| r.functor == functor && r.arity == arity && r.indices.contains(&index))
+}
+
 pub fn all_builtin_functors() -> Vec<(&'static str, &'static [usize])> {
     inventory::iter::<BuiltinFunctorSet>()
         .flat_map(|set| set.functors.iter().copied())
         .collect()
 }
+
+pub fn is_builtin_predicate(name: &str) -> bool {
+    inventory::iter::<BuiltinPredicate>().any(|p| p.name == name)
+}
+
+pub fn find_builtin_predicate(name: &str, arity: usize) -> Option<&'static BuiltinPredicate> {
+    inventory::iter::<BuiltinPredicate>().find(|p| p.name == name && p.arity == arity)
+}
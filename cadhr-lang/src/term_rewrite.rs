@@ -1,14 +1,17 @@
 use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::fmt;
+use std::path::PathBuf;
+use std::time::Instant;
 
 use crate::constraint::{ArithEq, ArithExpr, solve_constraints};
 use crate::parse::{
-    ArithOp, Bound, Clause, FixedPoint, QueryParam, ScopeId, ScopedTerm, SrcSpan, Term, first_span,
-    list, number, struc, var,
+    ArithOp, Bound, Clause, ERROR_TERM_DEPTH, FixedPoint, QueryParam, ScopeId, ScopedTerm, SrcSpan,
+    Term, first_span, list, number, string_lit, struc, var,
 };
 use crate::term_processor::{
-    all_builtin_functors, is_builtin_functor, is_builtin_functor_with_arity, should_resolve_args,
+    BuiltinPredicate, PredicateContext, all_builtin_functors, find_builtin_predicate,
+    is_builtin_functor, is_builtin_functor_with_arity, is_raw_builtin_arg, should_resolve_args,
 };
 
 pub type Env = HashMap<String, ScopedTerm>;
@@ -36,6 +39,40 @@ impl ScopedEnv {
 
 const RESOLVE_DEPTH_LIMIT: usize = 256;
 
+/// ゴールの書き換えの入れ子の上限。終わらない再帰を deadline を待たずにエラーにする
+const REWRITE_DEPTH_LIMIT: usize = 1024;
+
+/// 書き換えの再帰でスタックの残りがこれを下回ったら、新しいスタック領域を確保する
+const REWRITE_STACK_RED_ZONE: usize = 256 * 1024;
+const REWRITE_STACK_GROWTH: usize = 4 * 1024 * 1024;
+
+/// ExecuteOptions::max_term_nodes を指定しない場合の項のノード数上限
+pub const DEFAULT_MAX_TERM_NODES: usize = 1_000_000;
+
+fn exceeds_node_count<S>(term: &Term<S>, limit: usize) -> bool {
+    let mut count = 0;
+    let mut stack = vec![term];
+    while let Some(t) = stack.pop() {
+        count += 1;
+        if count > limit {
+            return true;
+        }
+        match t {
+            Term::Struct { args, .. } => stack.extend(args),
+            Term::List { items, tail } => {
+                stack.extend(items);
+                stack.extend(tail.as_deref());
+            }
+            Term::InfixExpr { left, right, .. } | Term::Constraint { left, right } => {
+                stack.push(left);
+                stack.push(right);
+            }
+            Term::Var { .. } | Term::Number { .. } | Term::StringLit { .. } => {}
+        }
+    }
+    false
+}
+
 /// envを参照して変数を再帰的に解決する。
 pub fn resolve(term: &ScopedTerm, env: &ScopedEnv) -> ScopedTerm {
     resolve_inner(term, env, 0)
@@ -80,7 +117,7 @@ fn resolve_inner(term: &ScopedTerm, env: &ScopedEnv, depth: usize) -> ScopedTerm
                 left: Box::new(new_left),
                 right: Box::new(new_right),
             };
-            if let Some(val) = try_fold_number_literals(&new_term) {
+            if let EvalResult::Value(val) = eval_arith(&new_term, false) {
                 number(val)
             } else {
                 new_term
@@ -98,15 +135,19 @@ fn resolve_inner(term: &ScopedTerm, env: &ScopedEnv, depth: usize) -> ScopedTerm
                 .collect(),
             span: *span,
         },
-        Term::List { items, tail } => Term::List {
-            items: items
-                .iter()
-                .map(|i| resolve_inner(i, env, depth + 1))
-                .collect(),
-            tail: tail
-                .as_ref()
-                .map(|t| Box::new(resolve_inner(t, env, depth + 1))),
-        },
+        Term::List { items, tail } => {
+            let mut list = Term::List {
+                items: items
+                    .iter()
+                    .map(|i| resolve_inner(i, env, depth + 1))
+                    .collect(),
+                tail: tail
+                    .as_ref()
+                    .map(|t| Box::new(resolve_inner(t, env, depth + 1))),
+            };
+            flatten_list_tail(&mut list);
+            list
+        }
         Term::Constraint { left, right } => Term::Constraint {
             left: Box::new(resolve_inner(left, env, depth + 1)),
             right: Box::new(resolve_inner(right, env, depth + 1)),
@@ -115,6 +156,20 @@ fn resolve_inner(term: &ScopedTerm, env: &ScopedEnv, depth: usize) -> ScopedTerm
     }
 }
 
+/// tail がリストのとき親の items に併合する: `[a | [b | T]]` → `[a, b | T]`
+fn flatten_list_tail<S>(term: &mut Term<S>) {
+    if let Term::List { items, tail } = term {
+        while let Some(Term::List {
+            items: tail_items,
+            tail: next,
+        }) = tail.as_deref_mut()
+        {
+            items.append(tail_items);
+            *tail = next.take();
+        }
+    }
+}
+
 /// Constraint内のVarから name→scope のマッピングを収集する
 fn collect_var_scopes_from_term(term: &ScopedTerm, scopes: &mut HashMap<String, ScopeId>) {
     match term {
@@ -149,6 +204,100 @@ fn is_metadata_term<S>(term: &Term<S>) -> bool {
         if is_builtin_functor(functor) && !should_resolve_args(functor))
 }
 
+inventory::submit! {
+    crate::term_processor::BuiltinFunctorSet {
+        functors: &[
+            ("dif", &[2]),
+            ("halt", &[0, 1]),
+            ("atom_to_term", &[3]),
+            ("binding", &[2]),
+        ],
+        resolve_args: false,
+    }
+}
+
+/// dif/2 の引数を現在の束縛で比べた結果
+enum DifState {
+    Identical,
+    Different,
+    Undecided,
+}
+
+fn dif_state(goal: &ScopedTerm, env: &ScopedEnv) -> Option<DifState> {
+    let Term::Struct { functor, args, .. } = goal else {
+        return None;
+    };
+    if functor != "dif" || args.len() != 2 {
+        return None;
+    }
+    let left = resolve(&args[0], env);
+    let right = resolve(&args[1], env);
+    Some(if left == right {
+        DifState::Identical
+    } else if unify(left, right, &mut env.clone()).is_ok() {
+        DifState::Undecided
+    } else {
+        DifState::Different
+    })
+}
+
+fn identical_dif_error(goal: &ScopedTerm, env: &ScopedEnv) -> RewriteError {
+    let goal = resolve(goal, env);
+    let message = match &goal {
+        Term::Struct { args, .. } => {
+            format!(
+                "dif: {} and {} are identical",
+                args[0].elided(ERROR_TERM_DEPTH),
+                args[1].elided(ERROR_TERM_DEPTH)
+            )
+        }
+        _ => "dif: arguments are identical".to_string(),
+    };
+    RewriteError {
+        message,
+        goal: Box::new(goal),
+    }
+}
+
+/// 項の引数の中に入れ子になった dif/2 を検査し、同一になったものがあればエラーにする
+fn check_nested_dif(term: &ScopedTerm, env: &ScopedEnv) -> Result<(), RewriteError> {
+    if let Some(DifState::Identical) = dif_state(term, env) {
+        return Err(identical_dif_error(term, env));
+    }
+    match term {
+        Term::Struct { args, .. } => args.iter().try_for_each(|a| check_nested_dif(a, env)),
+        Term::List { items, tail } => items
+            .iter()
+            .chain(tail.as_deref())
+            .try_for_each(|t| check_nested_dif(t, env)),
+        Term::InfixExpr { left, right, .. } | Term::Constraint { left, right } => {
+            check_nested_dif(left, env)?;
+            check_nested_dif(right, env)
+        }
+        Term::Var { .. } | Term::Number { .. } | Term::StringLit { .. } => Ok(()),
+    }
+}
+
+/// ゴール列に残った dif/2 を現在の束縛で検査する。
+/// 同一になったものはエラー、単一化できなくなったものは除去し、まだ決まらないものは残す。
+/// 他の項の引数に入れ子になった dif も同一になればエラーにする。
+fn check_dif_goals(goals: &mut Vec<ScopedTerm>, env: &ScopedEnv) -> Result<(), RewriteError> {
+    let mut kept = Vec::with_capacity(goals.len());
+    for goal in goals.drain(..) {
+        match dif_state(&goal, env) {
+            Some(DifState::Identical) => return Err(identical_dif_error(&goal, env)),
+            Some(DifState::Different) => {}
+            Some(DifState::Undecided) => kept.push(goal),
+            None => {
+                check_nested_dif(&goal, env)?;
+                kept.push(goal);
+            }
+        }
+    }
+    *goals = kept;
+    Ok(())
+}
+
 fn builtin_fact(functor: &str, arity: usize) -> Clause {
     let args = (0..arity)
         .map(|idx| var(format!("__builtin_arg_{}", idx)))
@@ -188,12 +337,17 @@ impl std::error::Error for UnifyError {}
 #[derive(Debug, Clone)]
 pub struct RewriteError {
     pub message: String,
-    pub goal: ScopedTerm,
+    pub goal: Box<ScopedTerm>,
 }
 
 impl fmt::Display for RewriteError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}: {:?}", self.message, self.goal)
+        write!(
+            f,
+            "{}: {}",
+            self.message,
+            self.goal.elided(ERROR_TERM_DEPTH)
+        )
     }
 }
 
@@ -222,18 +376,14 @@ impl CadhrError for RewriteError {
     }
 }
 
-fn collect_default_var_bindings(
-    term: &ScopedTerm,
-    bindings: &mut Vec<(String, ScopeId, FixedPoint)>,
-) {
+fn collect_default_var_bindings<'a>(term: &'a ScopedTerm, bindings: &mut Vec<&'a ScopedTerm>) {
     match term {
         Term::Var {
             name,
-            scope,
-            default_value: Some(value),
+            default_value: Some(_),
             ..
         } if name != "_" => {
-            bindings.push((name.clone(), *scope, *value));
+            bindings.push(term);
         }
         Term::Var { .. } => {}
         Term::StringLit { .. } => {}
@@ -262,78 +412,44 @@ fn collect_default_var_bindings(
     }
 }
 
-fn apply_default_var_bindings(term: &mut ScopedTerm, goals: &mut Vec<ScopedTerm>) {
+/// `5<W@10<20` のように範囲も持つ変数は、既定値が範囲外ならエラー
+fn apply_default_var_bindings(
+    term: &mut ScopedTerm,
+    goals: &mut Vec<ScopedTerm>,
+) -> Result<(), RewriteError> {
     let mut bindings = Vec::new();
     collect_default_var_bindings(term, &mut bindings);
     let mut env = ScopedEnv::new();
-    for (name, scope, value) in bindings {
-        env.insert(scope, name, number(value));
+    for var in bindings {
+        let Term::Var {
+            name,
+            scope,
+            default_value: Some(value),
+            min,
+            max,
+            ..
+        } = var
+        else {
+            unreachable!("collect_default_var_bindings only collects defaulted vars");
+        };
+        if !value_in_range(*value, *min, *max) {
+            return Err(RewriteError {
+                message: format!("default value {} of {} is out of range", value, name),
+                goal: Box::new(var.clone()),
+            });
+        }
+        env.insert(*scope, name.clone(), number(*value));
     }
     *term = resolve(term, &env);
     for goal in goals.iter_mut() {
         *goal = resolve(goal, &env);
     }
-}
-
-/// Number と InfixExpr だけで構成された算術式を畳み込む。
-/// Var は処理しない。unify の Var ハンドラが名前ベースの置換を伴って処理するため、
-/// ここで Number に変換すると置換が抜け落ちる。
-fn try_fold_number_literals<S>(term: &Term<S>) -> Option<FixedPoint> {
-    match term {
-        Term::Number { value } => Some(*value),
-        Term::InfixExpr { op, left, right } => {
-            let l = try_fold_number_literals(left)?;
-            let r = try_fold_number_literals(right)?;
-            Some(match op {
-                ArithOp::Add => l + r,
-                ArithOp::Sub => l - r,
-                ArithOp::Mul => l * r,
-                ArithOp::Div => l / r,
-            })
-        }
-        Term::Var { .. }
-        | Term::Struct { .. }
-        | Term::List { .. }
-        | Term::StringLit { .. }
-        | Term::Constraint { .. }
-        => None,
-    }
-}
-
-/// Var の default_value も数値として扱い算術式を評価する。
-/// unify 中では使わず、最終的な数値抽出（メッシュ生成など）で使う。
-pub fn try_eval_to_number<S>(term: &Term<S>) -> Option<FixedPoint> {
-    match term {
-        Term::Number { value } => Some(*value),
-        Term::Var {
-            default_value: Some(value),
-            ..
-        } => Some(*value),
-        Term::InfixExpr { op, left, right } => {
-            let l = try_eval_to_number(left)?;
-            let r = try_eval_to_number(right)?;
-            Some(match op {
-                ArithOp::Add => l + r,
-                ArithOp::Sub => l - r,
-                ArithOp::Mul => l * r,
-                ArithOp::Div => l / r,
-            })
-        }
-        Term::Var {
-            default_value: None,
-            ..
-        }
-        | Term::Struct { .. }
-        | Term::List { .. }
-        | Term::StringLit { .. }
-        | Term::Constraint { .. }
-        => None,
-    }
+    Ok(())
 }
 
 /// 算術式をインプレースで畳み込み、可能なら数値に置き換える
 pub fn fold_number_literals_in_place<S>(term: &mut Term<S>) {
-    if let Some(val) = try_fold_number_literals(term) {
+    if let EvalResult::Value(val) = eval_arith(term, false) {
         *term = number(val);
     } else {
         match term {
@@ -366,6 +482,65 @@ pub fn fold_number_literals_in_place<S>(term: &mut Term<S>) {
     }
 }
 
+/// 算術式の評価結果
+pub enum EvalResult<'a, S> {
+    Value(FixedPoint),
+    /// 未束縛変数を含むため、束縛されるまで遅延する
+    Unbound,
+    /// 数値になり得ない項を含む
+    TypeError(&'a Term<S>),
+    /// 0 除算を含む（中身は除算の項）
+    DivisionByZero(&'a Term<S>),
+}
+
+impl<S> EvalResult<'_, S> {
+    pub fn value(&self) -> Option<FixedPoint> {
+        match self {
+            EvalResult::Value(v) => Some(*v),
+            _ => None,
+        }
+    }
+}
+
+/// Number と InfixExpr で構成された算術式を評価する。
+/// use_default_values が true なら Var の default_value も数値として扱う。これは最終的な数値抽出
+/// （メッシュ生成など）用で、unify 中は false にする。ここで Var を Number にすると、
+/// unify の Var ハンドラが行う名前ベースの置換が抜け落ちるため。
+pub fn eval_arith<S>(term: &Term<S>, use_default_values: bool) -> EvalResult<'_, S> {
+    match term {
+        Term::Number { value } => EvalResult::Value(*value),
+        Term::Var {
+            default_value: Some(value),
+            ..
+        } if use_default_values => EvalResult::Value(*value),
+        Term::Var { .. } => EvalResult::Unbound,
+        Term::InfixExpr { op, left, right } => match (
+            eval_arith(left, use_default_values),
+            eval_arith(right, use_default_values),
+        ) {
+            (EvalResult::TypeError(t), _) | (_, EvalResult::TypeError(t)) => {
+                EvalResult::TypeError(t)
+            }
+            (EvalResult::DivisionByZero(t), _) | (_, EvalResult::DivisionByZero(t)) => {
+                EvalResult::DivisionByZero(t)
+            }
+            (EvalResult::Value(l), EvalResult::Value(r)) => match op {
+                ArithOp::Add => EvalResult::Value(l + r),
+                ArithOp::Sub => EvalResult::Value(l - r),
+                ArithOp::Mul => EvalResult::Value(l * r),
+                ArithOp::Div => l
+                    .checked_div(r)
+                    .map_or_else(|| EvalResult::DivisionByZero(term), EvalResult::Value),
+            },
+            _ => EvalResult::Unbound,
+        },
+        Term::Struct { .. }
+        | Term::List { .. }
+        | Term::StringLit { .. }
+        | Term::Constraint { .. } => EvalResult::TypeError(term),
+    }
+}
+
 /// occurs check: 変数varが項term内に出現するか
 fn occurs_check_scoped(var_name: &str, var_scope: ScopeId, term: &ScopedTerm) -> bool {
     match term {
@@ -753,15 +928,35 @@ pub fn unify(
             t2 = number(*value);
         }
 
-        if let Some(val) = try_fold_number_literals(&t1) {
+        if let EvalResult::Value(val) = eval_arith(&t1, false) {
             t1 = number(val);
         }
-        if let Some(val) = try_fold_number_literals(&t2) {
+        if let EvalResult::Value(val) = eval_arith(&t2, false) {
             t2 = number(val);
         }
 
-        // 算術式がまだ評価できない場合は遅延
+        // 算術式がまだ評価できない場合は遅延。数値以外を含む場合は束縛が進んでも解けないので即エラー
         if matches!(t1, Term::InfixExpr { .. }) || matches!(t2, Term::InfixExpr { .. }) {
+            let arith_error = [&t1, &t2]
+                .into_iter()
+                .find_map(|t| match eval_arith(t, false) {
+                    EvalResult::TypeError(bad) => Some(format!(
+                        "arithmetic type error: {} is not a number",
+                        bad.elided(ERROR_TERM_DEPTH)
+                    )),
+                    EvalResult::DivisionByZero(bad) => Some(format!(
+                        "arithmetic error: division by zero in {}",
+                        bad.elided(ERROR_TERM_DEPTH)
+                    )),
+                    EvalResult::Value(_) | EvalResult::Unbound => None,
+                });
+            if let Some(message) = arith_error {
+                return Err(UnifyError {
+                    message,
+                    term1: t1,
+                    term2: t2,
+                });
+            }
             deferred.push((t1, t2));
             continue;
         }
@@ -813,7 +1008,11 @@ pub fn unify(
             ) if name != "_" => {
                 if occurs_check_scoped(name, *scope, &t2) {
                     return Err(UnifyError {
-                        message: format!("occurs check failed: {} occurs in {:?}", name, t2),
+                        message: format!(
+                            "occurs check failed: {} occurs in {}",
+                            name,
+                            t2.elided(ERROR_TERM_DEPTH)
+                        ),
                         term1: t1,
                         term2: t2,
                     });
@@ -942,7 +1141,11 @@ pub fn unify(
             }
             _ => {
                 return Err(UnifyError {
-                    message: format!("cannot unify {:?} with {:?}", t1, t2),
+                    message: format!(
+                        "cannot unify {} with {}",
+                        t1.elided(ERROR_TERM_DEPTH),
+                        t2.elided(ERROR_TERM_DEPTH)
+                    ),
                     term1: t1,
                     term2: t2,
                 });
@@ -963,7 +1166,10 @@ pub fn unify(
     for (d1, d2) in deferred {
         let t1 = resolve(&d1, env);
         let t2 = resolve(&d2, env);
-        match (try_fold_number_literals(&t1), try_fold_number_literals(&t2)) {
+        match (
+            eval_arith(&t1, false).value(),
+            eval_arith(&t2, false).value(),
+        ) {
             (Some(n1), Some(n2)) => {
                 if n1 != n2 {
                     return Err(UnifyError {
@@ -981,7 +1187,11 @@ pub fn unify(
                     });
                 } else {
                     return Err(UnifyError {
-                        message: format!("cannot unify {:?} with {:?}", t1, t2),
+                        message: format!(
+                            "cannot unify {} with {}",
+                            t1.elided(ERROR_TERM_DEPTH),
+                            t2.elided(ERROR_TERM_DEPTH)
+                        ),
                         term1: t1,
                         term2: t2,
                     });
@@ -993,6 +1203,34 @@ pub fn unify(
     Ok(constraints)
 }
 
+/// unify_terms の結果。変数名 → 束縛先
+pub type Substitution = HashMap<String, ScopedTerm>;
+
+/// 2 つの項の最汎単一化子。同名の変数は両項で同じ変数として扱う。
+/// 失敗したとき、または算術制約が解けずに残るときは None
+pub fn unify_terms(a: &Term, b: &Term) -> Option<Substitution> {
+    let a = assign_scope_to_term(a.clone(), 0);
+    let b = assign_scope_to_term(b.clone(), 0);
+    let mut env = ScopedEnv::new();
+    let constraints = unify(a.clone(), b.clone(), &mut env).ok()?;
+    if !constraints.is_empty() {
+        return None;
+    }
+    let mut substitution = Substitution::new();
+    for term in [&a, &b] {
+        collect_named_vars(term, &mut |v| {
+            let Term::Var { name, .. } = v else {
+                return;
+            };
+            let value = resolve(v, &env);
+            if !matches!(&value, Term::Var { name: bound, .. } if bound == name) {
+                substitution.insert(name.clone(), value);
+            }
+        });
+    }
+    Some(substitution)
+}
+
 /// goals 内の Constraint を評価し、解けたものは除去、解けないものは残す
 /// 全 Constraint をまとめて SolverState に渡し、連立方程式として解く
 fn try_resolve_constraints(goals: &mut Vec<ScopedTerm>) -> Result<(), RewriteError> {
@@ -1017,7 +1255,7 @@ fn try_resolve_constraints(goals: &mut Vec<ScopedTerm>) -> Result<(), RewriteErr
         let idx = constraint_indices[0];
         RewriteError {
             message: format!("constraint contradiction: {}", msg),
-            goal: goals.remove(idx),
+            goal: Box::new(goals.remove(idx)),
         }
     })?;
 
@@ -1061,7 +1299,7 @@ fn assign_scope_to_clause(clause: Clause, scope_id: ScopeId) -> Clause<ScopeId>
     }
 }
 
-fn assign_scope_to_term(term: Term, scope_id: ScopeId) -> ScopedTerm {
+pub(crate) fn assign_scope_to_term(term: Term, scope_id: ScopeId) -> ScopedTerm {
     match term {
         Term::Var {
             name,
@@ -1111,26 +1349,203 @@ fn assign_scope_to_term(term: Term, scope_id: ScopeId) -> ScopedTerm {
     }
 }
 
+/// 期限の確認を行う間隔（clause の試行回数）
+const DEADLINE_CHECK_INTERVAL: usize = 1024;
+
+/// clause の試行回数を数え、スコープIDを採番する。期限付き実行では一定回数ごとに時刻を確認する。
+/// halt/0, halt/1 に到達した場合は終了コードを保持し、以降のゴールは書き換えない。
+struct ClauseCounter {
+    count: usize,
+    deadline: Option<Instant>,
+    next_deadline_check: usize,
+    halt_code: Option<i64>,
+    /// Some の場合、複数の節にマッチしたゴールの警告を (name/arity, メッセージ) で集める
+    ambiguity_warnings: Option<Vec<(String, String)>>,
+    max_term_nodes: usize,
+    /// 書き換え中のゴールの入れ子の深さ
+    depth: usize,
+    /// 組み込み述語に渡す PredicateContext::include_paths
+    include_paths: Vec<PathBuf>,
+}
+
+impl ClauseCounter {
+    fn new(deadline: Option<Instant>) -> Self {
+        Self {
+            count: 0,
+            deadline,
+            next_deadline_check: 0,
+            halt_code: None,
+            ambiguity_warnings: None,
+            max_term_nodes: DEFAULT_MAX_TERM_NODES,
+            depth: 0,
+            include_paths: Vec::new(),
+        }
+    }
+
+    /// 置換後の body が大きくなりすぎていないか（f(T, T) の繰り返しなどで指数的に膨らむのを止める）
+    fn check_term_size(&self, body: &[ScopedTerm]) -> Result<(), RewriteError> {
+        let Some(goal) = body
+            .iter()
+            .find(|g| exceeds_node_count(g, self.max_term_nodes))
+        else {
+            return Ok(());
+        };
+        let name = match goal {
+            Term::Struct { functor, args, .. } => format!("{}/{}", functor, args.len()),
+            _ => "goal".to_string(),
+        };
+        Err(RewriteError {
+            message: format!(
+                "term too large: {} has more than {} nodes",
+                name, self.max_term_nodes
+            ),
+            goal: Box::new(struc(name, vec![])),
+        })
+    }
+
+    fn next_scope(&mut self) -> ScopeId {
+        self.count += 1;
+        self.count
+    }
+
+    fn check_deadline(&mut self, goal: &ScopedTerm) -> Result<(), RewriteError> {
+        let Some(deadline) = self.deadline else {
+            return Ok(());
+        };
+        if self.count < self.next_deadline_check {
+            return Ok(());
+        }
+        self.next_deadline_check = self.count + DEADLINE_CHECK_INTERVAL;
+        if Instant::now() >= deadline {
+            return Err(RewriteError {
+                message: "evaluation timed out".to_string(),
+                goal: Box::new(goal.clone()),
+            });
+        }
+        Ok(())
+    }
+}
+
+#[derive(PartialEq, Eq, Hash)]
+enum Literal<'a> {
+    Number(FixedPoint),
+    Atom(&'a str),
+    String(&'a str),
+}
+
+fn literal<S>(term: &Term<S>) -> Option<Literal<'_>> {
+    match term {
+        Term::Number { value } => Some(Literal::Number(*value)),
+        Term::Struct { functor, args, .. } if args.is_empty() => Some(Literal::Atom(functor)),
+        Term::StringLit { value } => Some(Literal::String(value)),
+        _ => None,
+    }
+}
+
+/// 節の (functor, アリティ) と第1引数のリテラルによる索引。実行ごとに一度だけ作る
+struct ClauseIndex<'a> {
+    clauses: &'a [Clause],
+    /// 頭部が Struct でないゴール用の全節
+    all: Vec<usize>,
+    predicates: HashMap<(&'a str, usize), PredicateIndex<'a>>,
+}
+
+#[derive(Default)]
+struct PredicateIndex<'a> {
+    all: Vec<usize>,
+    /// 第1引数がリテラルでない節。どのリテラルのゴールにもマッチしうる
+    unindexed: Vec<usize>,
+    /// 第1引数リテラルごとの候補。unindexed の節も節の順を保って含む
+    by_first_arg: HashMap<Literal<'a>, Vec<usize>>,
+}
+
+impl<'a> ClauseIndex<'a> {
+    fn new(clauses: &'a [Clause]) -> Self {
+        let mut predicates: HashMap<(&'a str, usize), PredicateIndex<'a>> = HashMap::new();
+        for (idx, clause) in clauses.iter().enumerate() {
+            let head = match clause {
+                Clause::Fact(head) | Clause::Rule { head, .. } => head,
+                Clause::Use { .. } => continue,
+            };
+            let Term::Struct { functor, args, .. } = head else {
+                continue;
+            };
+            let entry = predicates
+                .entry((functor.as_str(), args.len()))
+                .or_default();
+            entry.all.push(idx);
+            match args.first().and_then(literal) {
+                Some(lit) => {
+                    let unindexed = &entry.unindexed;
+                    entry
+                        .by_first_arg
+                        .entry(lit)
+                        .or_insert_with(|| unindexed.clone())
+                        .push(idx);
+                }
+                None => {
+                    entry.unindexed.push(idx);
+                    for bucket in entry.by_first_arg.values_mut() {
+                        bucket.push(idx);
+                    }
+                }
+            }
+        }
+        Self {
+            clauses,
+            all: (0..clauses.len()).collect(),
+            predicates,
+        }
+    }
+
+    /// goal と単一化しうる節の番号（節の順）
+    fn candidates<'s>(&'s self, goal: &'s ScopedTerm) -> &'s [usize] {
+        let Term::Struct { functor, args, .. } = goal else {
+            return &self.all;
+        };
+        let Some(predicate) = self.predicates.get(&(functor.as_str(), args.len())) else {
+            return &[];
+        };
+        match args.first().and_then(literal) {
+            Some(lit) => predicate
+                .by_first_arg
+                .get(&lit)
+                .unwrap_or(&predicate.unindexed),
+            None => &predicate.all,
+        }
+    }
+}
+
 /// 単一の項をルールとマッチさせ、マッチすれば(書き換え後の項, 置換適用済みbody)を返す
 /// マッチしなければNoneを返す
 fn try_rewrite_single_with_result(
-    db: &[Clause],
-    clause_counter: &mut usize,
+    db: &ClauseIndex,
+    clause_counter: &mut ClauseCounter,
     term: &ScopedTerm,
     other_goals: &mut Vec<ScopedTerm>,
     shared_env: &mut ScopedEnv,
 ) -> Option<(ScopedTerm, Vec<ScopedTerm>)> {
-    for clause in db.iter() {
-        *clause_counter += 1;
-        let scoped = assign_scope_to_clause(clause.clone(), *clause_counter);
+    let candidates = db.candidates(term);
+    for (pos, &idx) in candidates.iter().enumerate() {
+        let clause = &db.clauses[idx];
+        let scope_id = clause_counter.next_scope();
+        let scoped = assign_scope_to_clause(clause.clone(), scope_id);
         let (head, body) = match scoped {
             Clause::Fact(t) => (t, vec![]),
             Clause::Rule { head, body } => (head, body),
             Clause::Use { .. } => continue,
         };
 
+        let chosen_head = clause_counter
+            .ambiguity_warnings
+            .is_some()
+            .then(|| head.clone());
         let mut trial_env = shared_env.clone();
         if let Ok(constraints) = unify(term.clone(), head, &mut trial_env) {
+            if let Some(chosen_head) = chosen_head {
+                let rest = candidates[pos + 1..].iter().map(|&i| &db.clauses[i]);
+                warn_if_ambiguous(clause_counter, term, &chosen_head, rest, shared_env);
+            }
             *shared_env = trial_env;
             let resolved_term = resolve(term, shared_env);
             let resolved_body: Vec<ScopedTerm> =
@@ -1147,18 +1562,193 @@ fn try_rewrite_single_with_result(
     None
 }
 
+/// 曖昧さの警告に並べる、使われなかった節の頭部の上限
+const MAX_LISTED_AMBIGUOUS_CLAUSES: usize = 5;
+
+/// バックトラックしないので、後続の節にもマッチするゴールは先頭の節だけが使われる。
+/// その場合に使われなかった候補を警告に残す（同じ述語につき一度だけ）。
+/// ゴールで値が決まっている引数について、使った節の頭部を包含する節（基底ケースの後ろの一般の節など）は
+/// 意図した書き分けなので数えない
+fn warn_if_ambiguous<'a>(
+    clause_counter: &mut ClauseCounter,
+    term: &ScopedTerm,
+    chosen_head: &ScopedTerm,
+    rest: impl Iterator<Item = &'a Clause>,
+    env: &ScopedEnv,
+) {
+    let Term::Struct { functor, args, .. } = term else {
+        return;
+    };
+    let key = format!("{}/{}", functor, args.len());
+    match clause_counter.ambiguity_warnings.as_ref() {
+        Some(w) if !w.iter().any(|(k, _)| *k == key) => {}
+        _ => return,
+    }
+    let bound: Vec<bool> = args
+        .iter()
+        .map(|a| !matches!(resolve(a, env), Term::Var { .. }))
+        .collect();
+    let chosen_inputs = bound_args(chosen_head, &bound);
+    // 候補の頭部は単一化を試すだけなので、まだ払い出していないスコープを共有して番号を消費しない
+    let scratch_scope = clause_counter.count + 1;
+    let mut ignored = Vec::new();
+    for clause in rest {
+        let Some(head) = scoped_head(clause, scratch_scope) else {
+            continue;
+        };
+        if let (Some(general), Some(specific)) = (bound_args(&head, &bound), &chosen_inputs)
+            && head_subsumes(&general, specific)
+        {
+            continue;
+        }
+        if unify(term.clone(), head.clone(), &mut env.clone()).is_ok() {
+            ignored.push(head);
+        }
+    }
+    if ignored.is_empty() {
+        return;
+    }
+    let mut listed: Vec<String> = ignored
+        .iter()
+        .take(MAX_LISTED_AMBIGUOUS_CLAUSES)
+        .map(|h| h.elided(ERROR_TERM_DEPTH).to_string())
+        .collect();
+    if ignored.len() > MAX_LISTED_AMBIGUOUS_CLAUSES {
+        listed.push(format!(
+            "... and {} more",
+            ignored.len() - MAX_LISTED_AMBIGUOUS_CLAUSES
+        ));
+    }
+    let message = format!(
+        "ambiguous goal {} ({}): using {} and ignoring {}",
+        resolve(term, env).elided(ERROR_TERM_DEPTH),
+        key,
+        chosen_head.elided(ERROR_TERM_DEPTH),
+        listed.join(", ")
+    );
+    if let Some(warnings) = clause_counter.ambiguity_warnings.as_mut() {
+        warnings.push((key, message));
+    }
+}
+
+fn scoped_head(clause: &Clause, scope_id: ScopeId) -> Option<ScopedTerm> {
+    match assign_scope_to_clause(clause.clone(), scope_id) {
+        Clause::Fact(t) | Clause::Rule { head: t, .. } => Some(t),
+        Clause::Use { .. } => None,
+    }
+}
+
+/// 頭部の引数のうち bound の位置のものをリストにする。そのような引数がなければ None
+fn bound_args(head: &ScopedTerm, bound: &[bool]) -> Option<ScopedTerm> {
+    let Term::Struct { args, .. } = head else {
+        return None;
+    };
+    let items: Vec<ScopedTerm> = args
+        .iter()
+        .zip(bound)
+        .filter(|(_, b)| **b)
+        .map(|(a, _)| a.clone())
+        .collect();
+    (!items.is_empty()).then(|| list(items, None))
+}
+
+/// general が specific の変数を束縛せずに specific と単一化できるか
+fn head_subsumes(general: &ScopedTerm, specific: &ScopedTerm) -> bool {
+    let mut env = ScopedEnv::new();
+    match unify(specific.clone(), general.clone(), &mut env) {
+        Ok(constraints) if constraints.is_empty() => {}
+        _ => return false,
+    }
+    // specific の相異なる変数が相異なる変数のまま残っていれば包含
+    let mut renaming: Vec<((String, ScopeId), (String, ScopeId))> = Vec::new();
+    let mut subsumes = true;
+    collect_named_vars(specific, &mut |v| {
+        let Term::Var { name, scope, .. } = v else {
+            return;
+        };
+        let key = (name.clone(), *scope);
+        let target = match resolve(v, &env) {
+            Term::Var { name, scope, .. } => (name, scope),
+            _ => {
+                subsumes = false;
+                return;
+            }
+        };
+        match renaming.iter().find(|(k, t)| *k == key || *t == target) {
+            Some((k, t)) => subsumes &= *k == key && *t == target,
+            None => renaming.push((key, target)),
+        }
+    });
+    subsumes
+}
+
 /// 項を深さ優先で再帰的に書き換える
 /// 書き換えが成功すれば書き換え後の項のリストを返す（複数になる場合がある）
 /// other_goals は書き換え中に発生した変数束縛を反映するため
 fn rewrite_term_recursive(
-    db: &[Clause],
-    clause_counter: &mut usize,
+    db: &ClauseIndex,
+    clause_counter: &mut ClauseCounter,
+    term: ScopedTerm,
+    other_goals: &mut Vec<ScopedTerm>,
+    shared_env: &mut ScopedEnv,
+) -> Result<Vec<ScopedTerm>, RewriteError> {
+    if clause_counter.depth >= REWRITE_DEPTH_LIMIT {
+        return Err(RewriteError {
+            message: format!(
+                "rewrite depth limit ({}) exceeded, possible infinite recursion",
+                REWRITE_DEPTH_LIMIT
+            ),
+            goal: Box::new(term),
+        });
+    }
+    clause_counter.depth += 1;
+    let result = stacker::maybe_grow(REWRITE_STACK_RED_ZONE, REWRITE_STACK_GROWTH, || {
+        rewrite_goal(db, clause_counter, term, other_goals, shared_env)
+    });
+    clause_counter.depth -= 1;
+    result
+}
+
+fn rewrite_goal(
+    db: &ClauseIndex,
+    clause_counter: &mut ClauseCounter,
     term: ScopedTerm,
     other_goals: &mut Vec<ScopedTerm>,
     shared_env: &mut ScopedEnv,
 ) -> Result<Vec<ScopedTerm>, RewriteError> {
+    if clause_counter.halt_code.is_some() {
+        return Ok(vec![]);
+    }
+    clause_counter.check_deadline(&term)?;
+
+    if let Term::Struct { functor, args, .. } = &term
+        && functor == "halt"
+        && args.len() <= 1
+    {
+        let code = match args.first().map(|a| resolve(a, shared_env)) {
+            None => 0,
+            Some(Term::Number { value }) if value.is_integral() => value.to_i64_checked().unwrap(),
+            Some(_) => {
+                return Err(RewriteError {
+                    message: "halt/1 expects an integer exit code".to_string(),
+                    goal: Box::new(term),
+                });
+            }
+        };
+        clause_counter.halt_code = Some(code);
+        return Ok(vec![]);
+    }
+
+    if let Term::Struct { functor, args, .. } = &term
+        && functor == "atom_to_term"
+        && args.len() == 3
+    {
+        eval_atom_to_term(clause_counter, &term, other_goals, shared_env)?;
+        return Ok(vec![]);
+    }
+
     let mut term = term;
-    apply_default_var_bindings(&mut term, other_goals);
+    apply_default_var_bindings(&mut term, other_goals)?;
 
     // range付きVarがゴールとして出現: 値の範囲チェックのみ行い、結果は返さない
     if let Term::Var {
@@ -1173,7 +1763,7 @@ fn rewrite_term_recursive(
                 if !value_in_range(*dv, *min, *max) {
                     return Err(RewriteError {
                         message: format!("value {} is out of range", dv),
-                        goal: term,
+                        goal: Box::new(term),
                     });
                 }
             }
@@ -1181,6 +1771,17 @@ fn rewrite_term_recursive(
         }
     }
 
+    if let Term::Struct {
+        ref functor,
+        ref args,
+        ..
+    } = term
+        && let Some(predicate) = find_builtin_predicate(functor, args.len())
+    {
+        eval_builtin_predicate(db, clause_counter, predicate, term, other_goals, shared_env)?;
+        return Ok(vec![]);
+    }
+
     // ビルトインファンクターは引数を解決してそのまま返す（builtin factとのunifyを避ける）
     if let Term::Struct {
         ref functor,
@@ -1203,6 +1804,7 @@ fn rewrite_term_recursive(
     if let Some((resolved_term, body)) =
         try_rewrite_single_with_result(db, clause_counter, &term, other_goals, shared_env)
     {
+        clause_counter.check_term_size(&body)?;
         if body.is_empty() {
             let functor_name = match &resolved_term {
                 Term::Struct { functor, .. } => Some(functor.as_str()),
@@ -1252,6 +1854,7 @@ fn rewrite_term_recursive(
                     shared_env,
                 )?;
                 all_resolved.extend(resolved);
+                check_dif_goals(&mut all_resolved, shared_env)?;
 
                 // 置換が適用された remaining_body と other_goals を復元
                 remaining_body = temp_other_goals.drain(0..remaining_body.len()).collect();
@@ -1288,7 +1891,7 @@ fn rewrite_term_recursive(
             if left_shapes.len() != 1 || right_shapes.len() != 1 {
                 return Err(RewriteError {
                     message: "InfixExpr operand resolved to multiple terms".to_string(),
-                    goal: Term::InfixExpr {
+                    goal: Box::new(Term::InfixExpr {
                         op,
                         left: Box::new(left_shapes.into_iter().next().unwrap_or(Term::Number {
                             value: FixedPoint::from_int(0),
@@ -1296,7 +1899,7 @@ fn rewrite_term_recursive(
                         right: Box::new(right_shapes.into_iter().next().unwrap_or(Term::Number {
                             value: FixedPoint::from_int(0),
                         })),
-                    },
+                    }),
                 });
             }
 
@@ -1314,7 +1917,7 @@ fn rewrite_term_recursive(
             } else {
                 Err(RewriteError {
                     message: "InfixExpr contains non-builtin terms after rewriting".to_string(),
-                    goal: new_term,
+                    goal: Box::new(new_term),
                 })
             }
         }
@@ -1332,11 +1935,11 @@ fn rewrite_term_recursive(
             }
             Err(RewriteError {
                 message: "no clause matches goal".to_string(),
-                goal: Term::Struct {
+                goal: Box::new(Term::Struct {
                     functor,
                     args,
                     span,
-                },
+                }),
             })
         }
         // その他の項（Number, Var, List など）はそのまま
@@ -1346,7 +1949,7 @@ fn rewrite_term_recursive(
             } else {
                 Err(RewriteError {
                     message: "no clause matches goal".to_string(),
-                    goal: other,
+                    goal: Box::new(other),
                 })
             }
         }
@@ -1356,8 +1959,8 @@ fn rewrite_term_recursive(
 /// ビルトインファンクタの引数内にある項を1つに解決する。
 /// リテラル/変数はそのまま、リストは中身を再帰的に解決、それ以外は書き換えて1つに解決する。
 fn resolve_builtin_arg(
-    db: &[Clause],
-    clause_counter: &mut usize,
+    db: &ClauseIndex,
+    clause_counter: &mut ClauseCounter,
     term: ScopedTerm,
     other_goals: &mut Vec<ScopedTerm>,
     shared_env: &mut ScopedEnv,
@@ -1376,20 +1979,8 @@ fn resolve_builtin_arg(
                 tail,
             })
         }
-        Term::Struct {
-            functor,
-            args,
-            span,
-        } if is_builtin_functor(&functor) => {
-            let resolved_args = args
-                .into_iter()
-                .map(|arg| resolve_builtin_arg(db, clause_counter, arg, other_goals, shared_env))
-                .collect::<Result<Vec<_>, _>>()?;
-            Ok(Term::Struct {
-                functor,
-                args: resolved_args,
-                span,
-            })
+        Term::Struct { ref functor, .. } if is_builtin_functor(functor) => {
+            resolve_builtin_fact_args(db, clause_counter, term, other_goals, shared_env)
         }
         Term::InfixExpr { op, left, right } => {
             let new_left = resolve_builtin_arg(db, clause_counter, *left, other_goals, shared_env)?;
@@ -1420,9 +2011,9 @@ fn resolve_builtin_arg(
             if resolved.len() != 1 {
                 return Err(RewriteError {
                     message: "builtin argument resolved to multiple terms".to_string(),
-                    goal: resolved.into_iter().next().unwrap_or(Term::Number {
+                    goal: Box::new(resolved.into_iter().next().unwrap_or(Term::Number {
                         value: FixedPoint::from_int(0),
-                    }),
+                    })),
                 });
             }
             Ok(resolved.remove(0))
@@ -1430,9 +2021,126 @@ fn resolve_builtin_arg(
     }
 }
 
+/// 組み込み述語を評価し、返された値を引数と単一化して shared_env と other_goals に反映する
+fn eval_builtin_predicate(
+    db: &ClauseIndex,
+    clause_counter: &mut ClauseCounter,
+    predicate: &BuiltinPredicate,
+    term: ScopedTerm,
+    other_goals: &mut Vec<ScopedTerm>,
+    shared_env: &mut ScopedEnv,
+) -> Result<(), RewriteError> {
+    let Term::Struct { args, .. } = &term else {
+        unreachable!("builtin predicate goal must be a struct");
+    };
+    let resolved_args = args
+        .iter()
+        .map(|arg| {
+            let arg = resolve(arg, shared_env);
+            if predicate.resolve_args {
+                resolve_builtin_arg(db, clause_counter, arg, other_goals, shared_env)
+            } else {
+                Ok(arg)
+            }
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let ctx = PredicateContext {
+        include_paths: &clause_counter.include_paths,
+        deadline: clause_counter.deadline,
+    };
+    let outputs = (predicate.eval)(&resolved_args, &ctx).map_err(|message| RewriteError {
+        message,
+        goal: Box::new(term.clone()),
+    })?;
+
+    for (idx, value) in outputs {
+        let constraints =
+            unify(resolved_args[idx].clone(), value, shared_env).map_err(|e| RewriteError {
+                message: e.message,
+                goal: Box::new(term.clone()),
+            })?;
+        other_goals.extend(constraints);
+    }
+    *other_goals = other_goals.iter().map(|g| resolve(g, shared_env)).collect();
+    Ok(())
+}
+
+/// atom_to_term(Atom, Term, Bindings): Atom のテキストを項として読み、変数は新しいスコープに置く。
+/// Bindings は `binding("Name", Var)` のリスト（`_` は含めない）
+fn eval_atom_to_term(
+    clause_counter: &mut ClauseCounter,
+    term: &ScopedTerm,
+    other_goals: &mut Vec<ScopedTerm>,
+    shared_env: &mut ScopedEnv,
+) -> Result<(), RewriteError> {
+    let Term::Struct { args, .. } = term else {
+        unreachable!("atom_to_term goal must be a struct");
+    };
+    let fail = |message: String| RewriteError {
+        message,
+        goal: Box::new(term.clone()),
+    };
+    let text = match resolve(&args[0], shared_env) {
+        Term::StringLit { value } => value,
+        Term::Struct { functor, args, .. } if args.is_empty() => functor,
+        other => {
+            return Err(fail(format!(
+                "atom_to_term: argument 0 must be an atom or string, got {}",
+                other.elided(ERROR_TERM_DEPTH)
+            )));
+        }
+    };
+    let parsed = match crate::parse::parse_single_term(&text) {
+        Ok((parsed, rest)) if matches!(rest.trim(), "" | ".") => parsed,
+        _ => return Err(fail(format!("atom_to_term: cannot parse {:?}", text))),
+    };
+    let parsed = assign_scope_to_term(parsed, clause_counter.next_scope());
+
+    let mut bindings: Vec<ScopedTerm> = Vec::new();
+    let mut seen: Vec<String> = Vec::new();
+    collect_named_vars(&parsed, &mut |v| {
+        if let Term::Var { name, .. } = v
+            && !seen.contains(name)
+        {
+            seen.push(name.clone());
+            bindings.push(struc(
+                "binding".to_string(),
+                vec![string_lit(name.clone()), v.clone()],
+            ));
+        }
+    });
+
+    for (arg, value) in [(&args[1], parsed), (&args[2], list(bindings, None))] {
+        let constraints = unify(arg.clone(), value, shared_env).map_err(|e| fail(e.message))?;
+        other_goals.extend(constraints);
+    }
+    *other_goals = other_goals.iter().map(|g| resolve(g, shared_env)).collect();
+    Ok(())
+}
+
+/// `_` 以外の変数を出現順に訪れる
+pub(crate) fn collect_named_vars<S>(term: &Term<S>, visit: &mut impl FnMut(&Term<S>)) {
+    match term {
+        Term::Var { name, .. } if name != "_" => visit(term),
+        Term::InfixExpr { left, right, .. } | Term::Constraint { left, right } => {
+            collect_named_vars(left, visit);
+            collect_named_vars(right, visit);
+        }
+        Term::Struct { args, .. } => args.iter().for_each(|a| collect_named_vars(a, visit)),
+        Term::List { items, tail } => {
+            items.iter().for_each(|i| collect_named_vars(i, visit));
+            if let Some(t) = tail {
+                collect_named_vars(t, visit);
+            }
+        }
+        _ => {}
+    }
+}
+
 fn resolve_builtin_fact_args(
-    db: &[Clause],
-    clause_counter: &mut usize,
+    db: &ClauseIndex,
+    clause_counter: &mut ClauseCounter,
     term: ScopedTerm,
     other_goals: &mut Vec<ScopedTerm>,
     shared_env: &mut ScopedEnv,
@@ -1446,9 +2154,17 @@ fn resolve_builtin_fact_args(
         other => return Ok(other),
     };
 
+    let arity = args.len();
     let resolved_args = args
         .into_iter()
-        .map(|arg| resolve_builtin_arg(db, clause_counter, arg, other_goals, shared_env))
+        .enumerate()
+        .map(|(i, arg)| {
+            if is_raw_builtin_arg(&functor, arity, i) {
+                Ok(arg)
+            } else {
+                resolve_builtin_arg(db, clause_counter, arg, other_goals, shared_env)
+            }
+        })
         .collect::<Result<Vec<_>, _>>()?;
 
     Ok(Term::Struct {
@@ -1462,11 +2178,83 @@ pub fn execute(
     db: &mut [Clause],
     query: Vec<Term>,
 ) -> Result<(Vec<ScopedTerm>, ScopedEnv), RewriteError> {
-    let mut clause_counter: usize = 0;
+    let execution = execute_with_options(db, query, ExecuteOptions::default())?;
+    Ok((execution.terms, execution.env))
+}
+
+/// クエリ変数の束縛を `X = bob, Y = carol` の形で表示する。
+/// `__` で始まる内部変数と、値の決まらなかった変数は省く
+pub fn format_solution(query: &[Term], env: &ScopedEnv) -> String {
+    let mut names: Vec<String> = Vec::new();
+    for term in query {
+        collect_named_vars(term, &mut |v| {
+            if let Term::Var { name, .. } = v
+                && !name.starts_with("__")
+                && !names.contains(name)
+            {
+                names.push(name.clone());
+            }
+        });
+    }
+    names
+        .into_iter()
+        .filter_map(|name| {
+            let value = resolve(&assign_scope_to_term(var(name.clone()), 0), env);
+            let unbound = matches!(
+                &value,
+                Term::Var {
+                    default_value: None,
+                    min: None,
+                    max: None,
+                    ..
+                }
+            );
+            (!unbound).then(|| format!("{} = {:?}", name, value))
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// execute の結果。halt で停止した場合は halt_code に終了コードが入り、terms はそこまでの結果になる。
+#[derive(Debug)]
+pub struct Execution {
+    pub terms: Vec<ScopedTerm>,
+    pub env: ScopedEnv,
+    pub halt_code: Option<i64>,
+    /// ExecuteOptions::warn_ambiguous 指定時の警告
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ExecuteOptions {
+    /// 過ぎると "evaluation timed out" で打ち切る
+    pub deadline: Option<Instant>,
+    /// 複数の節にマッチしたゴール（先頭以外が無視される）を警告する
+    pub warn_ambiguous: bool,
+    /// 項のノード数上限。None なら DEFAULT_MAX_TERM_NODES
+    pub max_term_nodes: Option<usize>,
+    /// 形状を評価する組み込み述語が stl などの相対パスを探すディレクトリ
+    pub include_paths: Vec<PathBuf>,
+}
+
+pub fn execute_with_options(
+    db: &mut [Clause],
+    query: Vec<Term>,
+    options: ExecuteOptions,
+) -> Result<Execution, RewriteError> {
+    let mut clause_counter = ClauseCounter::new(options.deadline);
+    if options.warn_ambiguous {
+        clause_counter.ambiguity_warnings = Some(Vec::new());
+    }
+    if let Some(limit) = options.max_term_nodes {
+        clause_counter.max_term_nodes = limit;
+    }
+    clause_counter.include_paths = options.include_paths;
     let mut shared_env = ScopedEnv::new();
     let mut results = Vec::new();
     let mut db_with_builtins = db.to_vec();
     db_with_builtins.extend(builtin_cad_facts());
+    let index = ClauseIndex::new(&db_with_builtins);
 
     let scoped_query: Vec<ScopedTerm> = query
         .into_iter()
@@ -1476,7 +2264,7 @@ pub fn execute(
     for term in scoped_query {
         let mut other_goals = Vec::new();
         let resolved = rewrite_term_recursive(
-            &db_with_builtins,
+            &index,
             &mut clause_counter,
             term,
             &mut other_goals,
@@ -1487,18 +2275,36 @@ pub fn execute(
 
         // 各ゴールの rewrite 後に制約解決し、得られた束縛を後続に伝播
         try_resolve_constraints(&mut results)?;
+        check_dif_goals(&mut results, &shared_env)?;
+        if clause_counter.halt_code.is_some() {
+            break;
+        }
     }
 
-    // 解決済み Constraint を結果から除去
-    results.retain(|t| !matches!(t, Term::Constraint { .. }));
-
-    Ok((results, shared_env))
+    // 解決済み Constraint と、最後まで同一にならなかった dif を結果から除去
+    results.retain(|t| {
+        !matches!(t, Term::Constraint { .. })
+            && !matches!(t, Term::Struct { functor, .. } if functor == "dif")
+    });
+
+    Ok(Execution {
+        terms: results,
+        env: shared_env,
+        halt_code: clause_counter.halt_code,
+        warnings: clause_counter
+            .ambiguity_warnings
+            .into_iter()
+            .flatten()
+            .map(|(_, message)| message)
+            .collect(),
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::parse::{FixedPoint, arith_expr, database, query, struc, var};
+    use crate::test_util::{query_var_binding, solution};
 
     fn var_with_range(name: &str, min: Option<Bound>, max: Option<Bound>) -> Term {
         Term::Var {
@@ -1516,6 +2322,21 @@ mod tests {
         assign_scope_to_term(term, 0)
     }
 
+    #[test]
+    fn unify_terms_returns_most_general_unifier() {
+        let parse = |src| crate::parse::parse_single_term(src).unwrap().0;
+        let substitution = unify_terms(&parse("f(X, b)"), &parse("f(a, Y)")).unwrap();
+        let mut bindings: Vec<String> = substitution
+            .iter()
+            .map(|(name, value)| format!("{} = {:?}", name, value))
+            .collect();
+        bindings.sort();
+        assert_eq!(bindings, ["X = a", "Y = b"]);
+
+        assert!(unify_terms(&parse("f(a, X)"), &parse("f(b, Y)")).is_none());
+        assert!(unify_terms(&parse("f(X, X)"), &parse("f(a, b)")).is_none());
+    }
+
     fn run_success(db_src: &str, query_src: &str) -> Vec<String> {
         let mut db = database(db_src).expect("failed to parse db");
         let q = query(query_src).expect("failed to parse query").1;
@@ -1562,6 +2383,64 @@ mod tests {
         assert!(unify(t1, t2, &mut ScopedEnv::new()).is_err());
     }
 
+    #[test]
+    fn test_unify_arith_over_struct_is_type_error() {
+        use crate::parse::number_int;
+        let expr = arith_expr(ArithOp::Add, var("X".to_string()), number_int(1));
+        let err = unify(
+            scoped(expr),
+            scoped(struc("foo".to_string(), vec![])),
+            &mut ScopedEnv::new(),
+        )
+        .unwrap_err();
+        assert!(err.message.starts_with("arithmetic type error"));
+
+        let expr = arith_expr(ArithOp::Mul, struc("a".to_string(), vec![]), number_int(2));
+        let err = unify(
+            scoped(expr),
+            scoped(var("Y".to_string())),
+            &mut ScopedEnv::new(),
+        )
+        .unwrap_err();
+        assert!(err.message.starts_with("arithmetic type error"));
+    }
+
+    #[test]
+    fn test_division_by_zero_is_an_error_not_a_panic() {
+        use crate::parse::number_int;
+        let zero = arith_expr(ArithOp::Sub, number_int(2), number_int(2));
+        let expr = arith_expr(ArithOp::Div, number_int(1), zero);
+        let err = unify(
+            scoped(expr),
+            scoped(var("Y".to_string())),
+            &mut ScopedEnv::new(),
+        )
+        .unwrap_err();
+        assert!(
+            err.message
+                .starts_with("arithmetic error: division by zero")
+        );
+
+        assert_eq!(
+            run_success("", "cube(1 / 0, 1, 1)."),
+            vec!["cube((1 / 0), 1, 1)"]
+        );
+    }
+
+    #[test]
+    fn test_unify_arith_over_unbound_var_is_deferred() {
+        use crate::parse::number_int;
+        let expr = arith_expr(ArithOp::Add, var("X".to_string()), number_int(1));
+        let constraints = unify(
+            scoped(expr),
+            scoped(var("Y".to_string())),
+            &mut ScopedEnv::new(),
+        )
+        .unwrap();
+        assert_eq!(constraints.len(), 1);
+        assert!(matches!(constraints[0], Term::Constraint { .. }));
+    }
+
     // ===== RangeVar unify tests =====
 
     // ===== arithmetic tests =====
@@ -1710,6 +2589,48 @@ mod tests {
         assert_eq!(resolved, vec!["likes(fuwa, fuwa)"]);
     }
 
+    #[test]
+    fn integer_unifies_with_equal_decimal() {
+        let resolved = run_success("f(5).", "f(5.0).");
+        assert_eq!(resolved, vec!["f(5)"]);
+        let resolved = run_success("f(5.0).", "f(5).");
+        assert_eq!(resolved, vec!["f(5)"]);
+        run_failure("f(5).", "f(5.1).");
+    }
+
+    // ===== dif tests =====
+
+    #[test]
+    fn dif_succeeds_when_binding_differs() {
+        let resolved = run_success("pick(b).", "dif(X, a), pick(X).");
+        assert_eq!(resolved, vec!["pick(b)"]);
+    }
+
+    #[test]
+    fn dif_fails_when_binding_makes_equal() {
+        run_failure("pick(a).", "dif(X, a), pick(X).");
+        run_failure("", "dif(a, a).");
+        run_failure("slot(s1).", "dif(X, s1), slot(X).");
+    }
+
+    #[test]
+    fn dif_checked_after_rule_body_binding() {
+        run_failure("pick(a). p :- dif(X, a), pick(X).", "p.");
+        run_success("pick(b). p :- dif(X, a), pick(X).", "p.");
+    }
+
+    #[test]
+    fn dif_checked_when_bound_in_nested_rule() {
+        let db = "pick(a). q(X) :- pick(X). p :- dif(X, a), q(X).";
+        run_failure(db, "p.");
+    }
+
+    #[test]
+    fn dif_checked_inside_other_terms() {
+        run_failure("pick(a). tag(T).", "tag(dif(X, a)), pick(X).");
+        run_success("pick(a). tag(T).", "tag(dif(X, b)), pick(X).");
+    }
+
     // ===== nested struct tests =====
 
     #[test]
@@ -1845,7 +2766,38 @@ mod tests {
     #[test]
     fn list_head_tail_pattern() {
         let resolved = run_success("f([a, b, c]).", "f([H|T]).");
-        assert_eq!(resolved, vec!["f([a | [b, c]])"]);
+        assert_eq!(resolved, vec!["f([a, b, c])"]);
+    }
+
+    #[test]
+    fn list_tail_bound_to_partial_list_is_flattened() {
+        let binding_of_l = |db_src: &str| {
+            let mut db = database(db_src).expect("failed to parse db");
+            let q = query("f(L).").expect("failed to parse query").1;
+            let (_, env) = execute(&mut db, q).expect("Expected success");
+            format!("{:?}", resolve(&scoped(var("L".to_string())), &env))
+        };
+        assert_eq!(
+            binding_of_l("f([a | X]) :- g(X). g([b | Y]) :- h(Y). h([c])."),
+            "[a, b, c]"
+        );
+        assert_eq!(
+            binding_of_l("f([a | X]) :- g(X). g([b | Y]) :- h(Y). h(_)."),
+            "[a, b | _]"
+        );
+    }
+
+    #[test]
+    fn flatten_list_tail_merges_nested_tails() {
+        let mut t: Term = list(
+            vec![struc("a".into(), vec![])],
+            Some(list(
+                vec![struc("b".into(), vec![])],
+                Some(list(vec![], None)),
+            )),
+        );
+        flatten_list_tail(&mut t);
+        assert_eq!(format!("{:?}", t), "[a, b]");
     }
 
     #[test]
@@ -2279,4 +3231,368 @@ mod tests {
             }
         );
     }
+
+    #[test]
+    fn value_in_fractional_exclusive_range() {
+        use crate::parse::Bound;
+        let lo = Some(Bound {
+            value: FixedPoint::from_int(0),
+            inclusive: false,
+        });
+        let hi = Some(Bound {
+            value: FixedPoint::from_int(1),
+            inclusive: false,
+        });
+        assert!(value_in_range(FixedPoint::from_hundredths(50), lo, hi));
+        assert!(value_in_range(FixedPoint::from_hundredths(1), lo, hi));
+        assert!(value_in_range(FixedPoint::from_hundredths(99), lo, hi));
+        assert!(!value_in_range(FixedPoint::from_int(0), lo, hi));
+        assert!(!value_in_range(FixedPoint::from_int(1), lo, hi));
+        assert!(!value_in_range(FixedPoint::from_hundredths(-1), lo, hi));
+        assert!(!value_in_range(FixedPoint::from_hundredths(101), lo, hi));
+    }
+
+    #[test]
+    fn default_inside_range_is_used() {
+        let mut db = database("box(W) :- cube(W, 1, 1).").unwrap();
+        let (_, q) = query("box(5 < W@10 < 20).").unwrap();
+        let (resolved, _) = execute(&mut db, q).unwrap();
+        assert_eq!(format!("{:?}", resolved), "[cube(10, 1, 1)]");
+    }
+
+    #[test]
+    fn default_outside_range_is_rejected() {
+        let mut db = database("box(W) :- cube(W, 1, 1).").unwrap();
+        let (_, q) = query("box(5 < W@30 < 20).").unwrap();
+        let err = execute(&mut db, q).unwrap_err();
+        assert_eq!(err.message, "default value 30 of W is out of range");
+    }
+
+    #[test]
+    fn intersect_bounds_compare_fractional_values() {
+        use crate::parse::Bound;
+        let bound = |h, inclusive| {
+            Some(Bound {
+                value: FixedPoint::from_hundredths(h),
+                inclusive,
+            })
+        };
+        assert_eq!(
+            intersect_min(bound(1, true), bound(0, false)),
+            bound(1, true)
+        );
+        assert_eq!(
+            intersect_max(bound(99, true), bound(100, false)),
+            bound(99, true)
+        );
+        assert_eq!(
+            intersect_min(bound(50, true), bound(50, false)),
+            bound(50, false)
+        );
+        assert_eq!(
+            intersect_max(bound(50, false), bound(50, true)),
+            bound(50, false)
+        );
+    }
+
+    // ===== deadline tests =====
+
+    #[test]
+    fn deadline_check_is_throttled_by_step_count() {
+        let goal = scoped(struc("a".to_string(), vec![]));
+        let mut counter = ClauseCounter::new(Some(Instant::now()));
+        assert!(counter.check_deadline(&goal).is_err());
+
+        // 期限切れでも、次の確認までの間は時刻を見ない
+        assert!(counter.check_deadline(&goal).is_ok());
+        for _ in 0..DEADLINE_CHECK_INTERVAL {
+            counter.next_scope();
+        }
+        let err = counter.check_deadline(&goal).unwrap_err();
+        assert_eq!(err.message, "evaluation timed out");
+
+        let mut unlimited = ClauseCounter::new(None);
+        assert!(unlimited.check_deadline(&goal).is_ok());
+    }
+
+    fn with_deadline(deadline: Instant) -> ExecuteOptions {
+        ExecuteOptions {
+            deadline: Some(deadline),
+            ..ExecuteOptions::default()
+        }
+    }
+
+    #[test]
+    fn execute_with_expired_deadline_times_out() {
+        let mut db = database("loop(X) :- loop(X).").unwrap();
+        let (_, q) = query("loop(1).").unwrap();
+        let err = execute_with_options(&mut db, q, with_deadline(Instant::now())).unwrap_err();
+        assert_eq!(err.message, "evaluation timed out");
+    }
+
+    #[test]
+    fn self_recursive_rule_hits_depth_limit_under_deadline() {
+        let mut db = database("spin(X) :- spin(X).").unwrap();
+        let (_, q) = query("spin(1).").unwrap();
+        let deadline = Instant::now() + std::time::Duration::from_secs(3);
+        let err = execute_with_options(&mut db, q, with_deadline(deadline)).unwrap_err();
+        assert!(
+            err.message.contains("rewrite depth limit"),
+            "{}",
+            err.message
+        );
+    }
+
+    #[test]
+    fn deep_finite_recursion_succeeds() {
+        let mut db = database("down(0, done). down(N, R) :- M = N - 1, down(M, R).").unwrap();
+        let (_, q) = query("down(600, R).").unwrap();
+        let (resolved, _) = execute(&mut db, q).unwrap();
+        assert_eq!(format!("{:?}", resolved), "[down(0, done)]");
+    }
+
+    #[test]
+    fn execute_with_future_deadline_succeeds() {
+        let mut db = database("box(X) :- cube(X, X, X).").unwrap();
+        let (_, q) = query("box(10).").unwrap();
+        let deadline = Instant::now() + std::time::Duration::from_secs(60);
+        let execution = execute_with_options(&mut db, q, with_deadline(deadline)).unwrap();
+        assert_eq!(format!("{:?}", execution.terms), "[cube(10, 10, 10)]");
+    }
+
+    // ===== atom_to_term tests =====
+
+    #[test]
+    fn atom_to_term_round_trips_term_to_atom() {
+        let q = "term_to_atom(cube(1, 2, 3), A), atom_to_term(A, T, B).";
+        assert_eq!(query_var_binding("", q, "A"), "\"cube(1, 2, 3)\"");
+        assert_eq!(query_var_binding("", q, "T"), "cube(1, 2, 3)");
+        assert_eq!(query_var_binding("", q, "B"), "[]");
+    }
+
+    #[test]
+    fn atom_to_term_binds_named_variables() {
+        let q = "atom_to_term(\"f(X, Y, X, _)\", T, [binding(\"X\", 1), binding(\"Y\", 2)]), \
+                 same(T, f(P, Q, R, S)).";
+        assert_eq!(query_var_binding("same(X, X).", q, "P"), "1");
+        assert_eq!(query_var_binding("same(X, X).", q, "Q"), "2");
+        assert_eq!(query_var_binding("same(X, X).", q, "R"), "1");
+    }
+
+    #[test]
+    fn atom_to_term_rejects_unparsable_text() {
+        run_failure("", "atom_to_term(\"cube(1,\", T, B).");
+        run_failure("", "atom_to_term(X, T, B).");
+    }
+
+    // ===== term size limit tests =====
+
+    #[test]
+    fn doubling_term_hits_node_limit() {
+        let src = "grow(0, T, T).\ngrow(N, T, R) :- grow(N - 1, f(T, T), R).";
+        let run = |n: u32| {
+            let mut db = database(src).unwrap();
+            let q = query(&format!("grow({}, a, R).", n)).unwrap().1;
+            let options = ExecuteOptions {
+                max_term_nodes: Some(1000),
+                ..ExecuteOptions::default()
+            };
+            execute_with_options(&mut db, q, options)
+        };
+        assert!(run(5).is_ok());
+        let err = run(40).unwrap_err();
+        assert_eq!(
+            err.message,
+            "term too large: grow/3 has more than 1000 nodes"
+        );
+    }
+
+    #[test]
+    fn exceeds_node_count_counts_every_subterm() {
+        let (t, _) = crate::parse::parse_single_term("f(a, [1, 2 | T], g(1 + 2))").unwrap();
+        assert!(!exceeds_node_count(&t, 10));
+        assert!(exceeds_node_count(&t, 9));
+    }
+
+    // ===== first-argument indexing tests =====
+
+    #[test]
+    fn bound_first_argument_skips_other_clauses() {
+        let colors = ["red", "green", "blue", "white", "black"];
+        let src: String = colors
+            .iter()
+            .enumerate()
+            .map(|(i, c)| format!("color({}, {}).\n", c, i))
+            .collect();
+        let clauses = database(&src).unwrap();
+        let db = ClauseIndex::new(&clauses);
+        let goal = scoped(query("color(blue, N).").unwrap().1.remove(0));
+        assert_eq!(db.candidates(&goal), &[2]);
+
+        let mut counter = ClauseCounter::new(None);
+        let mut env = ScopedEnv::new();
+        let (resolved, _) =
+            try_rewrite_single_with_result(&db, &mut counter, &goal, &mut vec![], &mut env)
+                .expect("Expected a match");
+        assert_eq!(format!("{:?}", resolved), "color(blue, 2)");
+        assert_eq!(counter.count, 1);
+
+        let unbound = scoped(query("color(C, N).").unwrap().1.remove(0));
+        let mut counter = ClauseCounter::new(None);
+        try_rewrite_single_with_result(
+            &db,
+            &mut counter,
+            &unbound,
+            &mut vec![],
+            &mut ScopedEnv::new(),
+        );
+        assert_eq!(counter.count, 1);
+        assert_eq!(solution(&src, "color(white, N)."), "N = 3");
+    }
+
+    #[test]
+    fn index_keeps_clause_order_with_variable_first_arguments() {
+        let clauses = database("g(X, 1).\ng(a, 2).\ng(Y, 3).\ng(b, 4).\nh(a).").unwrap();
+        let db = ClauseIndex::new(&clauses);
+        let candidates = |src: &str| {
+            let goal = scoped(query(src).unwrap().1.remove(0));
+            db.candidates(&goal).to_vec()
+        };
+        assert_eq!(candidates("g(a, K)."), [0, 1, 2]);
+        assert_eq!(candidates("g(c, K)."), [0, 2]);
+        assert_eq!(candidates("g(Z, K)."), [0, 1, 2, 3]);
+        assert_eq!(candidates("g(a)."), [] as [usize; 0]);
+    }
+
+    #[test]
+    fn first_argument_literals_must_match_kind() {
+        let db = "f(1, num).\nf(\"1\", str).\nf(one, atom).\nf(X, var).";
+        assert_eq!(solution(db, "f(one, K)."), "K = atom");
+        assert_eq!(solution(db, "f(\"1\", K)."), "K = str");
+        assert_eq!(solution(db, "f(1, K)."), "K = num");
+        assert_eq!(solution(db, "f(2, K)."), "K = var");
+    }
+
+    // ===== ambiguity warning tests =====
+
+    fn ambiguity_warnings(db_src: &str, query_src: &str) -> Vec<String> {
+        let mut db = database(db_src).expect("failed to parse db");
+        let q = query(query_src).expect("failed to parse query").1;
+        let options = ExecuteOptions {
+            warn_ambiguous: true,
+            ..ExecuteOptions::default()
+        };
+        execute_with_options(&mut db, q, options)
+            .expect("Expected success")
+            .warnings
+    }
+
+    #[test]
+    fn warns_when_two_clauses_match_shape_goal() {
+        let db = "part(cube(1, 1, 1)).\npart(sphere(2)).\nmain :- part(X), X.";
+        let warnings = ambiguity_warnings(db, "main.");
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("part/1"), "{}", warnings[0]);
+        assert!(warnings[0].contains("part(sphere(2))"), "{}", warnings[0]);
+    }
+
+    #[test]
+    fn ambiguity_warning_caps_ignored_clauses() {
+        let facts: String = (0..10).map(|i| format!("row({}).\n", i)).collect();
+        let db = format!("{}main :- row(X), cube(X, 1, 1).", facts);
+        let warnings = ambiguity_warnings(&db, "main.");
+        assert_eq!(warnings.len(), 1);
+        assert!(
+            warnings[0]
+                .ends_with("ignoring row(1), row(2), row(3), row(4), row(5), ... and 4 more"),
+            "{}",
+            warnings[0]
+        );
+    }
+
+    #[test]
+    fn no_warning_for_general_fallback_clause() {
+        let db = "color(red, 1).\ncolor(C, 0).\nmain :- color(red, N), cube(N, N, N).";
+        assert!(ambiguity_warnings(db, "main.").is_empty());
+
+        let db = "color(C, 0).\ncolor(red, 1).\nmain :- color(red, N), cube(N, N, N).";
+        let warnings = ambiguity_warnings(db, "main.");
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("color(red, 1)"), "{}", warnings[0]);
+    }
+
+    #[test]
+    fn fallback_must_not_merge_distinct_variables() {
+        let db = "pair(X, Y, diff).\npair(Z, Z, same).\nmain :- pair(p(A), p(A), K).";
+        assert_eq!(ambiguity_warnings(db, "main.").len(), 1);
+    }
+
+    #[test]
+    fn no_warning_for_single_match_or_when_disabled() {
+        let db = "size(small, 1).\nsize(large, 2).\nmain :- size(large, S), cube(S, S, S).";
+        assert!(ambiguity_warnings(db, "main.").is_empty());
+
+        let mut db = database("part(a).\npart(b).").unwrap();
+        let q = query("part(X).").unwrap().1;
+        let execution = execute_with_options(&mut db, q, ExecuteOptions::default()).unwrap();
+        assert!(execution.warnings.is_empty());
+    }
+
+    // ===== format_solution tests =====
+
+    #[test]
+    fn format_solution_lists_bound_query_vars() {
+        let db = "parent(tom, bob).\nparent(bob, carol).";
+        assert_eq!(solution(db, "parent(tom, X)."), "X = bob");
+        assert_eq!(
+            solution(db, "parent(tom, X), parent(X, Y)."),
+            "X = bob, Y = carol"
+        );
+    }
+
+    #[test]
+    fn format_solution_skips_unbound_vars() {
+        assert_eq!(solution("f(1, _).", "f(X, Y)."), "X = 1");
+        assert_eq!(solution("g.", "g."), "");
+    }
+
+    // ===== halt tests =====
+
+    fn run_halting(db_src: &str, query_src: &str) -> (Vec<String>, Option<i64>) {
+        let mut db = database(db_src).expect("failed to parse db");
+        let q = query(query_src).expect("failed to parse query").1;
+        let execution =
+            execute_with_options(&mut db, q, ExecuteOptions::default()).expect("Expected success");
+        let terms = execution.terms.iter().map(|t| format!("{:?}", t)).collect();
+        (terms, execution.halt_code)
+    }
+
+    #[test]
+    fn halt_stops_remaining_query_goals() {
+        let (terms, code) = run_halting("", "cube(1, 1, 1), halt, sphere(2).");
+        assert_eq!(terms, vec!["cube(1, 1, 1)"]);
+        assert_eq!(code, Some(0));
+    }
+
+    #[test]
+    fn halt_with_code_inside_rule_body() {
+        let db =
+            "check(X) :- cube(X, X, X), halt(3), sphere(X).\nmain :- check(2), cylinder(1, 1).";
+        let (terms, code) = run_halting(db, "main.");
+        assert_eq!(terms, vec!["cube(2, 2, 2)"]);
+        assert_eq!(code, Some(3));
+    }
+
+    #[test]
+    fn no_halt_reports_none() {
+        let (terms, code) = run_halting("", "cube(1, 1, 1).");
+        assert_eq!(terms, vec!["cube(1, 1, 1)"]);
+        assert_eq!(code, None);
+    }
+
+    #[test]
+    fn halt_requires_integer_code() {
+        run_failure("", "halt(a).");
+        run_failure("", "halt(1.5).");
+    }
 }
@@ -0,0 +1,35 @@
+//! テスト用のクエリ実行ヘルパー
+
+use crate::parse::{ScopedTerm, database, query, var};
+use crate::term_rewrite::{ScopedEnv, assign_scope_to_term, execute, format_solution, resolve};
+
+/// db_src を読み込んで query_src を実行し、書き換え結果と束縛を返す。失敗したら panic
+pub(crate) fn run_query(db_src: &str, query_src: &str) -> (Vec<ScopedTerm>, ScopedEnv) {
+    let mut db = database(db_src).expect("failed to parse db");
+    let q = query(query_src).expect("failed to parse query").1;
+    execute(&mut db, q).expect("Expected success")
+}
+
+pub(crate) fn query_succeeds(db_src: &str, query_src: &str) -> bool {
+    let mut db = database(db_src).expect("failed to parse db");
+    let q = query(query_src).expect("failed to parse query").1;
+    execute(&mut db, q).is_ok()
+}
+
+/// クエリ変数 name の束縛
+pub(crate) fn binding(env: &ScopedEnv, name: &str) -> ScopedTerm {
+    resolve(&assign_scope_to_term(var(name.to_string()), 0), env)
+}
+
+/// クエリを実行し、クエリ変数 name の束縛を文字列で返す
+pub(crate) fn query_var_binding(db_src: &str, query_src: &str, name: &str) -> String {
+    let (_, env) = run_query(db_src, query_src);
+    format!("{:?}", binding(&env, name))
+}
+
+/// クエリを実行し、format_solution の表示を返す
+pub(crate) fn solution(db_src: &str, query_src: &str) -> String {
+    let q = query(query_src).expect("failed to parse query").1;
+    let (_, env) = run_query(db_src, query_src);
+    format_solution(&q, &env)
+}
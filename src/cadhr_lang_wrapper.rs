@@ -8,15 +8,19 @@ use crate::events::{
     GeneratePreviewRequest, PreviewGenerated,
 };
 use cadhr_lang::bom::BomExtractor;
-use cadhr_lang::manifold_bridge::{MeshGenerator, extract_control_points};
+use cadhr_lang::manifold_bridge::{MeshGenerator, StlCache, extract_control_points};
 use cadhr_lang::module::resolve_modules;
 use cadhr_lang::parse::{
     FileRegistry, SrcSpan, collect_query_params, database, parse_error_span, query as parse_query,
     substitute_query_params,
 };
 use cadhr_lang::term_processor::TermProcessor;
-use cadhr_lang::term_rewrite::{CadhrError, execute, infer_query_param_ranges};
+use cadhr_lang::term_rewrite::{
+    CadhrError, ExecuteOptions, execute_with_options, infer_query_param_ranges,
+};
 use manifold_rs::Mesh as RsMesh;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 fn format_error(
     label: &str,
@@ -37,9 +41,25 @@ fn format_error(
 
 pub struct CadhrLangPlugin;
 
+/// プレビュー生成時の書き換えの制限時間。超えると "evaluation timed out" エラーになる。
+#[derive(Resource, Clone, Copy)]
+pub struct EvalTimeout(pub Duration);
+
+impl Default for EvalTimeout {
+    fn default() -> Self {
+        Self(Duration::from_secs(5))
+    }
+}
+
+/// プレビューの再生成をまたいで共有する stl の読み込み結果
+#[derive(Resource, Default)]
+struct SharedStlCache(Arc<StlCache>);
+
 impl Plugin for CadhrLangPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<MeshTasks>()
+            .init_resource::<EvalTimeout>()
+            .init_resource::<SharedStlCache>()
             .init_resource::<CollisionTasks>()
             .add_systems(Update, (handle_mesh_tasks, handle_collision_tasks));
     }
@@ -64,13 +84,19 @@ struct CollisionTasks(Vec<Task<CollisionJobResult>>);
 fn handle_mesh_tasks(
     mut ev_requests: MessageReader<GeneratePreviewRequest>,
     mut tasks: ResMut<MeshTasks>,
+    timeout: Res<EvalTimeout>,
+    stl_cache: Res<SharedStlCache>,
     mut ev_output: MessageWriter<CadhrLangOutput>,
     mut ev_preview: MessageWriter<PreviewGenerated>,
 ) {
     let pool = AsyncComputeTaskPool::get();
     for req in ev_requests.read() {
         let req = req.clone();
-        tasks.0.push(pool.spawn(async move { run_mesh_job(req) }));
+        let timeout = timeout.0;
+        let stl_cache = stl_cache.0.clone();
+        tasks
+            .0
+            .push(pool.spawn(async move { run_mesh_job(req, timeout, stl_cache) }));
     }
     tasks.0.retain_mut(|task| {
         if let Some(result) = block_on(future::poll_once(task)) {
@@ -118,7 +144,12 @@ fn empty_mesh() -> Mesh {
     )
 }
 
-fn run_mesh_job(req: GeneratePreviewRequest) -> MeshJobResult {
+fn run_mesh_job(
+    req: GeneratePreviewRequest,
+    timeout: Duration,
+    stl_cache: Arc<StlCache>,
+) -> MeshJobResult {
+    let deadline = Instant::now() + timeout;
     let preview_id = req.preview_id;
     let db_src = req.database;
     let query = req.query;
@@ -174,11 +205,23 @@ fn run_mesh_job(req: GeneratePreviewRequest) -> MeshJobResult {
         let substituted = substitute_query_params(&query_terms, &values);
         logs.push(format!("Query terms: {:?}", substituted));
         logs.push(format!("Database clauses: {:#?}", db));
-        let (mut resolved, _env) =
-            execute(&mut db, substituted).map_err(|e| {
-                format_error("Rewrite error", &e.to_string(), e.span(), &file_registry)
-            })?;
+        let options = ExecuteOptions {
+            deadline: Some(deadline),
+            warn_ambiguous: true,
+            include_paths: req.include_paths.clone(),
+            ..ExecuteOptions::default()
+        };
+        let execution = execute_with_options(&mut db, substituted, options).map_err(|e| {
+            format_error("Rewrite error", &e.to_string(), e.span(), &file_registry)
+        })?;
+        let mut resolved = execution.terms;
         logs.push(format!("Resolved terms: {:?}", resolved));
+        for warning in &execution.warnings {
+            logs.push(format!("Warning: {}", warning));
+        }
+        if let Some(code) = execution.halt_code {
+            logs.push(format!("Halted with code {}", code));
+        }
 
         let control_points = extract_control_points(&mut resolved, &req.control_point_overrides);
         Ok((resolved, control_points, query_params))
@@ -203,24 +246,50 @@ fn run_mesh_job(req: GeneratePreviewRequest) -> MeshJobResult {
             });
 
         if resolved.is_empty() {
-            return Ok((empty_mesh(), vec![], control_points, bom_entries, query_params));
+            return Ok((
+                empty_mesh(),
+                vec![],
+                vec![],
+                control_points,
+                bom_entries,
+                query_params,
+            ));
         }
 
         let mesh_generator = MeshGenerator {
             include_paths: req.include_paths.clone(),
+            stl_cache,
         };
-        let (rs_mesh, evaluated_nodes) = mesh_generator
-            .process(&resolved)
-            .map_err(|e| {
+        let (rs_mesh, evaluated_nodes, warnings) =
+            mesh_generator.process(&resolved).map_err(|e| {
                 let span = e.span();
                 (format!("Mesh error: {}", e), span)
             })?;
         let mesh = rs_mesh_to_bevy_mesh(&rs_mesh).map_err(|e| (e, None))?;
-        Ok((mesh, evaluated_nodes, control_points, bom_entries, query_params))
+        Ok((
+            mesh,
+            evaluated_nodes,
+            warnings,
+            control_points,
+            bom_entries,
+            query_params,
+        ))
     });
 
     let preview = match mesh_result {
-        Ok((mesh, evaluated_nodes, control_points, bom_entries, query_params)) => {
+        Ok((mesh, evaluated_nodes, warnings, control_points, bom_entries, query_params)) => {
+            if !warnings.is_empty() {
+                outputs.push(CadhrLangOutput {
+                    preview_id: None,
+                    message: warnings
+                        .iter()
+                        .map(|w| format!("Warning: {}", w))
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                    is_error: false,
+                    error_span: None,
+                });
+            }
             PreviewGenerated {
                 preview_id,
                 query,
@@ -285,9 +354,15 @@ fn run_collision_job(req: GenerateCollisionPreviewRequest) -> CollisionJobResult
             &mut file_registry,
         )
         .map_err(|e| (format!("Module error: {}", e), None))?;
-        let (resolved, _) = execute(&mut db, query_terms).map_err(|e| {
-            format_error("Rewrite error", &e.to_string(), e.span(), &file_registry)
-        })?;
+        let options = ExecuteOptions {
+            include_paths: req.include_paths.clone(),
+            ..ExecuteOptions::default()
+        };
+        let resolved = execute_with_options(&mut db, query_terms, options)
+            .map_err(|e| {
+                format_error("Rewrite error", &e.to_string(), e.span(), &file_registry)
+            })?
+            .terms;
 
         use cadhr_lang::manifold_bridge::{ConversionError, Model3D};
         let exprs: Vec<Model3D> = resolved